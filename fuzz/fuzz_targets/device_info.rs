@@ -0,0 +1,9 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+extern crate meterreader_models;
+use meterreader_models::DeviceInfo;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = DeviceInfo::from_response(data);
+});
@@ -0,0 +1,9 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+extern crate meterreader_models;
+use meterreader_models::DeviceName;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = DeviceName::from_response(data);
+});
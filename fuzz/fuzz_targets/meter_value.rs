@@ -6,4 +6,6 @@ use meterreader_models::MeterValue;
 
 fuzz_target!(|data: &[u8]| {
     let _ = MeterValue::from_data(data);
+    let (service_data, manufacturer_data) = data.split_at(data.len().min(3));
+    let _ = MeterValue::from_advertisement(service_data, Some(manufacturer_data));
 });
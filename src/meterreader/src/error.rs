@@ -0,0 +1,116 @@
+use bluer::Address;
+use meterreader_models::ParseError;
+use std::{fmt, io};
+
+#[derive(Debug)]
+pub enum Error {
+    /// The meter rejected the password.
+    AuthFailed(Address),
+    /// The meter's response to a command couldn't be parsed, even after
+    /// re-issuing the command.
+    BadResponse(Address, ParseError),
+    Bluetooth(bluer::Error),
+    /// The device doesn't expose the GATT service used to send commands,
+    /// i.e. it probably isn't a meter.
+    CharacteristicsNotFound(Address),
+    /// Connecting to the meter failed, even after retrying.
+    ConnectFailed(Address, bluer::Error),
+    /// The meter only sent empty notifications in response to a command.
+    EmptyResponse(Address),
+    /// The meter's section info is inconsistent, so the timestamps of its
+    /// samples can't be trusted.
+    InconsistentSectionInfo(Address),
+    /// Ctrl-C was pressed, after which the connected meters were
+    /// disconnected.
+    Interrupted,
+    Io(io::Error),
+    /// Handling some meters failed, which has been logged already. Holds
+    /// their number and the exit code of the first failure.
+    MetersFailed {
+        count: usize,
+        exit_code: u8,
+    },
+    /// The meters given with `--address` that weren't found while scanning.
+    MetersNotFound(Vec<Address>),
+    /// The name to set is longer than meters accept, in bytes.
+    NameTooLong(usize),
+    /// There is no usable Bluetooth adapter, for the given reason.
+    NoAdapter(String),
+    /// The meter didn't respond to a command in time.
+    NoResponse(Address),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::AuthFailed(addr) => write!(f, "{addr} rejected the password"),
+            Error::BadResponse(addr, err) => {
+                write!(f, "{addr} sent a response that couldn't be parsed: {err}")
+            }
+            Error::Bluetooth(err) => write!(f, "Bluetooth error: {err}"),
+            Error::CharacteristicsNotFound(addr) => {
+                write!(f, "{addr} doesn't expose the SwitchBot GATT service")
+            }
+            Error::ConnectFailed(addr, err) => write!(f, "Could not connect to {addr}: {err}"),
+            Error::EmptyResponse(addr) => write!(f, "{addr} sent an empty response"),
+            Error::InconsistentSectionInfo(addr) => {
+                write!(f, "{addr} reported inconsistent section info")
+            }
+            Error::Interrupted => write!(f, "Interrupted"),
+            Error::Io(err) => write!(f, "I/O error: {err}"),
+            Error::MetersFailed { count, .. } => write!(f, "Failed to handle {count} meters"),
+            Error::MetersNotFound(addrs) => write!(
+                f,
+                "Meters not found: {}",
+                addrs
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Error::NameTooLong(len) => write!(
+                f,
+                "Name is {len} bytes long, meters accept at most {}",
+                meterreader_models::Command::MAX_NAME_LEN
+            ),
+            Error::NoAdapter(reason) => write!(f, "{reason}"),
+            Error::NoResponse(addr) => write!(f, "{addr} didn't respond in time"),
+        }
+    }
+}
+
+impl Error {
+    /// Returns the exit code to fail with, as listed in `--help`.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::NoAdapter(_) => 3,
+            Error::MetersNotFound(_) => 4,
+            Error::AuthFailed(_) | Error::CharacteristicsNotFound(_) | Error::ConnectFailed(..) => {
+                5
+            }
+            Error::BadResponse(..)
+            | Error::EmptyResponse(_)
+            | Error::InconsistentSectionInfo(_) => 6,
+            Error::NoResponse(_) => 7,
+            Error::MetersFailed { exit_code, .. } => *exit_code,
+            Error::Interrupted => 130,
+            Error::Bluetooth(_) | Error::Io(_) | Error::NameTooLong(_) => 1,
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<bluer::Error> for Error {
+    fn from(err: bluer::Error) -> Error {
+        Error::Bluetooth(err)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
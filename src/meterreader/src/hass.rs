@@ -0,0 +1,219 @@
+use bluer::Address;
+use serde_json::json;
+use std::io;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+use meterreader_models::MeterValue;
+
+use crate::output::json_number;
+
+/// The sensors set for every meter, as field name, unit and device class.
+const SENSORS: [(&str, &str, &str); 3] = [
+    ("temperature", "°C", "temperature"),
+    ("humidity", "%", "humidity"),
+    ("battery", "%", "battery"),
+];
+
+/// How many states may wait to be sent before new ones are dropped.
+const QUEUE_LEN: usize = 64;
+/// How long to wait for Home Assistant to answer a single request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long to wait for pending states to be sent when finishing.
+const FINISH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Sets the states of sensors in Home Assistant through its REST API, at
+/// `/api/states/sensor.<meter>_<field>`.
+///
+/// Requests are sent in the background, in order, so a slow or unreachable
+/// Home Assistant doesn't hold up reading meters. States are dropped with a
+/// warning while it can't keep up.
+pub struct Client {
+    states: mpsc::Sender<(String, serde_json::Value)>,
+    sender: JoinHandle<()>,
+}
+
+impl Client {
+    pub fn new(url: &str, token: &str) -> io::Result<Client> {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(io::Error::other)?;
+        let (states, mut pending) = mpsc::channel::<(String, serde_json::Value)>(QUEUE_LEN);
+        let url = url.trim_end_matches('/').to_string();
+        let token = token.to_string();
+        let sender = tokio::spawn(async move {
+            while let Some((entity_id, state)) = pending.recv().await {
+                let result = client
+                    .post(format!("{url}/api/states/{entity_id}"))
+                    .bearer_auth(&token)
+                    .json(&state)
+                    .send()
+                    .await;
+                match result {
+                    Ok(response) if response.status().is_success() => {}
+                    Ok(response) => warn!(
+                        "Home Assistant rejected the state of {entity_id}: {}",
+                        response.status()
+                    ),
+                    Err(err) => warn!("Could not set the state of {entity_id}: {err}"),
+                }
+            }
+        });
+        Ok(Client { states, sender })
+    }
+
+    /// Sets the states of the sensors of a meter, named after its `label`
+    /// from the name map if it has one.
+    pub fn update(&self, addr: Address, label: Option<&str>, value: &MeterValue) {
+        for (entity_id, state) in states(addr, label, value) {
+            // The sender only stops once the client is finished, so sending
+            // only fails if the queue is full.
+            if self.states.try_send((entity_id, state)).is_err() {
+                warn!(%addr, "Too many pending Home Assistant states, dropping them");
+                return;
+            }
+        }
+    }
+
+    /// Sends all pending states. If that takes longer than
+    /// [`FINISH_TIMEOUT`], the remaining states are dropped.
+    pub async fn finish(mut self) {
+        drop(self.states);
+        match tokio::time::timeout(FINISH_TIMEOUT, &mut self.sender).await {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => warn!("Sending states to Home Assistant failed: {err}"),
+            Err(_) => {
+                warn!("Timed out sending states to Home Assistant, dropping them");
+                self.sender.abort();
+            }
+        }
+    }
+}
+
+/// Returns the entity IDs and request bodies of the sensors of a meter.
+fn states(
+    addr: Address,
+    label: Option<&str>,
+    value: &MeterValue,
+) -> Vec<(String, serde_json::Value)> {
+    let object_id = match label {
+        Some(label) => slug(label),
+        None => format!("meter_{}", addr.to_string().replace(':', "").to_lowercase()),
+    };
+    let name = label.map_or_else(|| format!("Meter {addr}"), str::to_string);
+    let states = [
+        json_number(value.temperature.0),
+        json!(value.humidity.0),
+        json!(value.battery),
+    ];
+    SENSORS
+        .into_iter()
+        .zip(states)
+        .map(|((field, unit, device_class), state)| {
+            let body = json!({
+                "state": state,
+                "attributes": {
+                    "friendly_name": format!("{name} {field}"),
+                    "unit_of_measurement": unit,
+                    "device_class": device_class,
+                    "state_class": "measurement",
+                },
+            });
+            (format!("sensor.{object_id}_{field}"), body)
+        })
+        .collect()
+}
+
+/// Turns a label into the lowercase letters, digits and underscores Home
+/// Assistant allows in object IDs.
+fn slug(label: &str) -> String {
+    let slug = label
+        .to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("_");
+    if slug.is_empty() {
+        "meter".to_string()
+    } else {
+        slug
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hass::{slug, states, Client};
+    use bluer::Address;
+    use meterreader_models::{Celsius, Humidity, MeterValue, TempUnit};
+    use serde_json::json;
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn builds_sensor_states() {
+        let addr = Address::new([0xd3, 0x4e, 0x07, 0x01, 0x02, 0x03]);
+        let value = MeterValue {
+            temperature: Celsius(24.9),
+            humidity: Humidity(40),
+            battery: 100,
+            display_unit: TempUnit::Celsius,
+        };
+
+        let states = states(addr, None, &value);
+        assert_eq!(states[0].0, "sensor.meter_d34e07010203_temperature");
+        assert_eq!(
+            states[0].1,
+            json!({
+                "state": 24.9,
+                "attributes": {
+                    "friendly_name": "Meter D3:4E:07:01:02:03 temperature",
+                    "unit_of_measurement": "°C",
+                    "device_class": "temperature",
+                    "state_class": "measurement",
+                },
+            })
+        );
+        assert_eq!(states[2].1["state"], 100);
+
+        let states = super::states(addr, Some("Living room"), &value);
+        assert_eq!(states[1].0, "sensor.living_room_humidity");
+        assert_eq!(
+            states[1].1["attributes"]["friendly_name"],
+            "Living room humidity"
+        );
+    }
+
+    #[tokio::test]
+    async fn drops_states_while_the_queue_is_full() {
+        let (states, mut pending) = mpsc::channel(4);
+        let client = Client {
+            states,
+            sender: tokio::spawn(async {}),
+        };
+        let value = MeterValue {
+            temperature: Celsius(24.9),
+            humidity: Humidity(40),
+            battery: 100,
+            display_unit: TempUnit::Celsius,
+        };
+        client.update(Address::any(), None, &value);
+        client.update(Address::any(), None, &value);
+        client.finish().await;
+
+        let mut sent = Vec::new();
+        while let Some((entity_id, _)) = pending.recv().await {
+            sent.push(entity_id);
+        }
+        assert_eq!(sent.len(), 4);
+        assert_eq!(sent[3], "sensor.meter_000000000000_temperature");
+    }
+
+    #[test]
+    fn slugifies_labels() {
+        assert_eq!(slug("Garage"), "garage");
+        assert_eq!(slug("Kid's room (2nd floor)"), "kid_s_room_2nd_floor");
+        assert_eq!(slug("°°"), "meter");
+    }
+}
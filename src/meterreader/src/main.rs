@@ -1,15 +1,40 @@
 use bluer::{gatt::remote::Characteristic, Adapter, AdapterEvent, Address, Device};
-use chrono::{Duration, Local, TimeZone};
-use clap::Parser;
+use chrono::{Duration, Local};
+use futures::stream::FuturesUnordered;
 use futures::{pin_mut, StreamExt};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::future::Future;
+use std::ops::Range;
+use std::rc::Rc;
 use std::time::Instant;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tracing::{debug, error, info, warn};
 
-use meterreader_models::{MeterSampleValue, MeterSectionInfo, MeterValue};
+use meterreader_models::{
+    live::{ADVERTISEMENT_SERVICE_UUID, MANUFACTURER_ID},
+    stats, AlarmThresholds, BatteryState, Celsius, Command, DeviceInfo, DeviceName, Humidity,
+    MeterSampleValue, MeterSectionInfo, MeterValue, PairMode, ParseError, ResponseCode,
+};
 
-// 0000fd3d-0000-1000-8000-00805f9b34fb
-const ADVERTISEMENT_SERVICE_UUID: uuid::Uuid =
-    uuid::Uuid::from_u128(0x0000_fd3d_0000_1000_8000_0080_5f9b_34fb_u128);
+use crate::error::Error;
+use crate::progress::Progress;
+
+mod error;
+#[cfg(feature = "hass")]
+mod hass;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+mod output;
+#[cfg(feature = "parquet")]
+mod parquet_file;
+mod progress;
+mod prometheus;
+mod replay;
+#[cfg(feature = "serve")]
+mod serve;
+#[cfg(feature = "sqlite")]
+mod sqlite;
 
 // cba20d00-224d-11e6-9fb8-0002a5d5c51b
 const SERVICE_UUID: uuid::Uuid =
@@ -23,131 +48,653 @@ const WRITE_CHAR_UUID: uuid::Uuid =
 const READ_CHAR_UUID: uuid::Uuid =
     uuid::Uuid::from_u128(0xcba2_0003_224d_11e6_9fb8_0002_a5d5_c51b_u128);
 
-const RESPONSE_OK: u8 = 1;
-const CMD_SET_TIME: u8 = 5;
-const CMD_READ_INDEX_INFO: u8 = 59;
-const CMD_READ_SAMPLE_INFO: u8 = 60;
-
 const SAMPLE_COUNT: u8 = 6;
 
+/// After how many blocks of samples in a row failing to read a dump gives up
+/// on the rest.
+const MAX_CONSECUTIVE_BLOCK_FAILURES: u32 = 3;
+
+/// The most sections of its log read from a meter with `--all-sections`.
+const MAX_SECTIONS: u8 = 8;
+
+/// How often in a row reconnecting to a meter that dropped the connection is
+/// tried without any command getting through in between.
+const MAX_RECONNECTS: u32 = 3;
+
+const CONNECT_BACKOFF_BASE: std::time::Duration = std::time::Duration::from_millis(250);
+const DEFAULT_COMMAND_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+const SERVICES_RESOLVE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+const SERVICES_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+#[derive(Clone, Debug)]
+struct MeterConfig {
+    connect_retries: u32,
+    /// Whether to keep using the characteristics found when connecting.
+    connect_cache: bool,
+    command_retries: u32,
+    command_timeout: std::time::Duration,
+    pipeline_depth: usize,
+    /// The MTU below which a warning is logged.
+    min_mtu: usize,
+    password: Option<String>,
+    /// Whether to print every raw response to stderr.
+    raw: bool,
+    /// Meters currently connected, so they can be disconnected on interrupt.
+    connected: Rc<RefCell<BTreeSet<Address>>>,
+}
+
+impl MeterConfig {
+    fn new(args: &cli::Args) -> MeterConfig {
+        MeterConfig {
+            connect_retries: args.connect_retries,
+            connect_cache: !args.no_connect_cache,
+            command_retries: args.command_retries,
+            command_timeout: args
+                .command_timeout
+                .map_or(DEFAULT_COMMAND_TIMEOUT, |timeout| timeout.to_std().unwrap()),
+            pipeline_depth: args.pipeline_depth.into(),
+            min_mtu: args.min_mtu.map_or_else(default_min_mtu, usize::from),
+            password: args.password.clone(),
+            raw: args.raw,
+            connected: Rc::default(),
+        }
+    }
+}
+
+/// Returns the smallest MTU that fits the response to reading a block of
+/// samples into a single notification, after the 3 bytes of its ATT header.
+fn default_min_mtu() -> usize {
+    Command::read_samples(0, SAMPLE_COUNT)
+        .response_len()
+        .unwrap_or_default()
+        + 3
+}
+
 struct Meter {
     device: Device,
+    config: MeterConfig,
     read_char: Option<Characteristic>,
     write_char: Option<Characteristic>,
+    /// The MTU of the last notification subscription, so it is only logged
+    /// when it changes.
+    mtu: Option<usize>,
 }
 
 impl Meter {
-    fn new(adapter: &Adapter, addr: Address) -> bluer::Result<Meter> {
+    fn new(adapter: &Adapter, addr: Address, config: &MeterConfig) -> bluer::Result<Meter> {
         Ok(Meter {
             device: adapter.device(addr)?,
+            config: config.clone(),
             read_char: None,
             write_char: None,
+            mtu: None,
         })
     }
 
-    async fn connect(&mut self) -> bluer::Result<()> {
-        if self.read_char.is_none() {
-            self.device.connect().await?;
-            if let Some((read_char, write_char)) = find_characteristics(&self.device).await? {
-                self.read_char = Some(read_char);
-                self.write_char = Some(write_char);
+    #[tracing::instrument(skip_all, fields(addr = %self.device.address()))]
+    async fn connect(&mut self) -> error::Result<()> {
+        if self.read_char.is_some() {
+            return Ok(());
+        }
+
+        let mut attempt = 0;
+        loop {
+            let result = self.try_connect().await;
+            if matches!(result, Ok(true)) {
+                return self.authenticate().await;
+            }
+            if attempt >= self.config.connect_retries {
+                return match result {
+                    Ok(_) => {
+                        self.device.disconnect().await?;
+                        Err(Error::CharacteristicsNotFound(self.device.address()))
+                    }
+                    Err(err) => Err(Error::ConnectFailed(self.device.address(), err)),
+                };
+            }
+
+            attempt += 1;
+            let delay = backoff_delay(attempt);
+            if let Err(err) = result {
+                warn!(
+                    "Connecting failed ({}), retrying in {:?} (attempt {}/{})",
+                    err, delay, attempt, self.config.connect_retries
+                );
+            } else {
+                warn!(
+                    "Characteristics not resolved yet, retrying in {:?} (attempt {}/{})",
+                    delay, attempt, self.config.connect_retries
+                );
             }
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    async fn try_connect(&mut self) -> bluer::Result<bool> {
+        // Another tool or a leftover session may still hold a connection,
+        // which connecting again doesn't cope with well.
+        if self.device.is_connected().await? {
+            debug!("Already connected");
+        } else {
+            self.device.connect().await?;
+        }
+        self.await_services_resolved().await?;
+        if let Some((read_char, write_char)) = find_characteristics(&self.device).await? {
+            self.read_char = Some(read_char);
+            self.write_char = Some(write_char);
+            self.config
+                .connected
+                .borrow_mut()
+                .insert(self.device.address());
+            info!("Connected");
+            Ok(true)
+        } else {
+            Ok(false)
         }
+    }
 
+    /// Waits up to [`SERVICES_RESOLVE_TIMEOUT`] for bluez to finish
+    /// discovering the meter's GATT services, so they aren't looked up while
+    /// the list is still incomplete.
+    async fn await_services_resolved(&self) -> bluer::Result<()> {
+        let deadline = tokio::time::Instant::now() + SERVICES_RESOLVE_TIMEOUT;
+        while !self.device.is_services_resolved().await? {
+            if tokio::time::Instant::now() >= deadline {
+                debug!("Services not resolved yet, looking them up anyway");
+                break;
+            }
+            tokio::time::sleep(SERVICES_POLL_INTERVAL).await;
+        }
         Ok(())
     }
 
-    pub async fn read_section_info(&mut self) -> bluer::Result<Option<MeterSectionInfo>> {
-        let mut cmd = gen_cmd(CMD_READ_INDEX_INFO, 1);
-        cmd[3] = 0;
-        let response = self.exec(&cmd).await?;
-        Ok(MeterSectionInfo::from_response(&response))
+    /// Checks the configured password, if any, against the meter.
+    ///
+    /// Meters don't keep a session, so every command carries the password
+    /// anyway, but checking it right after connecting turns a wrong password
+    /// into a single clear error.
+    async fn authenticate(&mut self) -> error::Result<()> {
+        if self.config.password.is_none() {
+            return Ok(());
+        }
+        let mut response = Vec::with_capacity(1);
+        self.send(
+            &[Command::read_device_info()],
+            &Progress::hidden(),
+            &mut response,
+        )
+        .await?;
+        if ResponseCode::from_response(&response[0]).is_some_and(ResponseCode::is_ok) {
+            Ok(())
+        } else {
+            self.disconnect().await?;
+            Err(Error::AuthFailed(self.device.address()))
+        }
     }
 
-    pub async fn read_samples(
+    /// Sends commands to the connected meter, protected with the password if
+    /// one is configured.
+    ///
+    /// Writes up to `pipeline_depth` commands ahead of the response being
+    /// read, so the meter doesn't sit idle during each round trip. Responses
+    /// are appended to `responses` as they arrive, so they are kept even if a
+    /// later command fails.
+    async fn send(
         &mut self,
-        section_info: &MeterSectionInfo,
-        duration: Option<Duration>,
-    ) -> bluer::Result<Vec<MeterSampleValue>> {
-        let mut result = Vec::with_capacity(section_info.data_length.into());
-
-        let mut all_iter;
-        let mut last_n_iter;
-        let samples: &mut dyn Iterator<Item = u16> = {
-            all_iter = (0..(section_info.data_length / u16::from(SAMPLE_COUNT))
-                * u16::from(SAMPLE_COUNT))
-                .step_by(SAMPLE_COUNT.into());
-            if let Some(duration) = duration {
-                let samples_wanted: usize =
-                    <i64 as TryInto<usize>>::try_into(duration.num_seconds()).unwrap()
-                        / usize::from(section_info.interval);
-                let sample_count = usize::from(SAMPLE_COUNT);
-                last_n_iter = all_iter
-                    .rev()
-                    .take((samples_wanted + sample_count - 1) / sample_count)
-                    .rev();
-                &mut last_n_iter
-            } else {
-                &mut all_iter
-            }
+        cmds: &[Command],
+        progress: &Progress,
+        responses: &mut Vec<Vec<u8>>,
+    ) -> error::Result<()> {
+        let (Some(read_char), Some(write_char)) = (&self.read_char, &self.write_char) else {
+            return Err(Error::CharacteristicsNotFound(self.device.address()));
         };
-        for i in samples {
-            let mut cmd = gen_cmd(CMD_READ_SAMPLE_INFO, 4);
-            cmd[3] = 0;
-            cmd[4] = (i >> 8) as u8;
-            cmd[5] = (i & 0xff) as u8;
-            cmd[6] = SAMPLE_COUNT;
-            let response = self.exec(&cmd).await?;
-            if let Some(mut samples) = MeterSampleValue::from_response(&response) {
-                result.append(&mut samples);
+
+        // Subscribe before writing so no notification is missed.
+        let mut notify_io = read_char.notify_io().await?;
+        let mtu = notify_io.mtu();
+        if self.mtu.replace(mtu) != Some(mtu) {
+            debug!(mtu, "Negotiated MTU");
+            if mtu < self.config.min_mtu {
+                warn!(
+                    addr = %self.device.address(),
+                    "MTU of {mtu} bytes is below {} bytes, so responses are split across notifications and reassembled",
+                    self.config.min_mtu
+                );
             }
         }
+        let mut buf = vec![0; mtu];
+        let mut write_io = write_char.write_io().await?;
 
-        Ok(result)
-    }
+        let done = responses.len();
+        let mut sent = 0;
+        for cmd in cmds {
+            let in_flight = responses.len() - done + self.config.pipeline_depth;
+            for next in &cmds[sent..in_flight.min(cmds.len())] {
+                debug!(cmd = %hex::encode(next.as_bytes()), "Sending command");
+                let next = match &self.config.password {
+                    Some(password) => next.clone().with_password(password.as_bytes()),
+                    None => next.clone(),
+                };
+                write_command(&mut write_io, &next).await?;
+                sent += 1;
+            }
 
-    pub async fn set_time(&mut self) -> bluer::Result<()> {
-        let mut cmd = gen_cmd(CMD_SET_TIME, 10);
-        let i = cmd.len() - 10;
-        cmd[i] = 3;
-        cmd[i + 1] = 0;
-        for (j, byte) in Local::now().timestamp().to_be_bytes().iter().enumerate() {
-            cmd[i + 2 + j] = *byte;
+            // On timeout, returning drops the notification subscription.
+            let response = read_response(
+                self.device.address(),
+                cmd,
+                &mut notify_io,
+                &mut buf,
+                self.config.command_timeout,
+            )
+            .await?;
+            debug!(response = %hex::encode(&response), "Received response");
+            if self.config.raw {
+                print_raw(
+                    self.device.address(),
+                    responses.len() - done,
+                    cmd,
+                    &response,
+                );
+            }
+            responses.push(response);
+            progress.inc();
         }
-        let response = self.exec(&cmd).await?;
-        if response.is_empty() || response[0] != RESPONSE_OK {
-            println!("[WARNING] Got non-okay response when setting time");
+        Ok(())
+    }
+
+    /// Returns whether `err` means the meter dropped the connection, i.e.
+    /// it is a failed GATT operation or an ended notification stream and
+    /// bluez no longer lists the meter as connected.
+    async fn connection_lost(&self, err: &Error) -> bool {
+        is_link_error(err) && !self.device.is_connected().await.unwrap_or(true)
+    }
+
+    pub async fn disconnect(&mut self) -> bluer::Result<()> {
+        self.reset();
+        self.config
+            .connected
+            .borrow_mut()
+            .remove(&self.device.address());
+        self.device.disconnect().await
+    }
+}
+
+/// Returns whether `err` is of a kind a dropped connection shows up as.
+fn is_link_error(err: &Error) -> bool {
+    match err {
+        Error::Bluetooth(err) => matches!(
+            err.kind,
+            bluer::ErrorKind::Failed
+                | bluer::ErrorKind::NotFound
+                | bluer::ErrorKind::NotificationSessionStopped
+                | bluer::ErrorKind::ServicesUnresolved
+                | bluer::ErrorKind::Internal(bluer::InternalErrorKind::Io(_))
+        ),
+        Error::EmptyResponse(_) | Error::Io(_) | Error::NoResponse(_) => true,
+        _ => false,
+    }
+}
+
+/// A connection to a meter that commands can be sent over.
+trait Transport {
+    /// Sends a command and returns the meter's raw response.
+    async fn exec(&mut self, cmd: &Command) -> error::Result<Vec<u8>>;
+
+    /// Sends several commands and appends their raw responses to
+    /// `responses` in order, advancing `progress` with each response.
+    ///
+    /// If a command fails, the responses to the commands before it are kept.
+    /// This sends the commands one after another by default; transports can
+    /// override it to have several commands in flight at once.
+    async fn exec_pipelined(
+        &mut self,
+        cmds: &[Command],
+        progress: &Progress,
+        responses: &mut Vec<Vec<u8>>,
+    ) -> error::Result<()> {
+        for cmd in cmds {
+            responses.push(self.exec(cmd).await?);
+            progress.inc();
         }
         Ok(())
     }
 
-    async fn exec(&mut self, cmd: &[u8]) -> bluer::Result<Vec<u8>> {
-        self.connect().await?;
-        if let Some(read_char) = &self.read_char {
-            let mut notify_io = read_char.notify_io().await?;
-            let mut buf = vec![0; notify_io.mtu()];
-            let read_future = notify_io.read(&mut buf);
+    /// Returns how often a command is re-issued after a bad response.
+    fn command_retries(&self) -> u32;
 
-            let mut write_io = self.write_char.as_ref().unwrap().write_io().await?;
-            let _ = write_io.write(cmd).await?;
-            drop(write_io);
+    /// Returns the address of the meter, for errors.
+    fn address(&self) -> Address;
 
-            let read = read_future.await?;
-            drop(notify_io);
-            buf.truncate(read);
-            Ok(buf)
-        } else {
-            Ok(vec![])
+    /// Returns whether what was looked up when connecting is kept for later
+    /// commands, which `--no-connect-cache` turns off.
+    fn connect_cache(&self) -> bool {
+        true
+    }
+
+    /// Forgets what was looked up when connecting, so it is looked up again
+    /// before the next command.
+    fn reset(&mut self) {}
+
+    /// Prepares for sending the next command, forgetting what was looked up
+    /// when connecting unless that is cached.
+    fn before_command(&mut self) {
+        if !self.connect_cache() {
+            self.reset();
+        }
+    }
+}
+
+impl Transport for Meter {
+    async fn exec(&mut self, cmd: &Command) -> error::Result<Vec<u8>> {
+        let mut responses = Vec::with_capacity(1);
+        self.exec_pipelined(
+            std::slice::from_ref(cmd),
+            &Progress::hidden(),
+            &mut responses,
+        )
+        .await?;
+        Ok(responses.remove(0))
+    }
+
+    #[tracing::instrument(skip_all, fields(addr = %self.device.address()))]
+    async fn exec_pipelined(
+        &mut self,
+        cmds: &[Command],
+        progress: &Progress,
+        responses: &mut Vec<Vec<u8>>,
+    ) -> error::Result<()> {
+        self.before_command();
+        let done = responses.len();
+        let mut reconnects = 0;
+        loop {
+            let before = responses.len();
+            self.connect().await?;
+            // After reconnecting, resume with the first command that failed.
+            let remaining = &cmds[responses.len() - done..];
+            let Err(err) = self.send(remaining, progress, responses).await else {
+                return Ok(());
+            };
+            if responses.len() > before {
+                reconnects = 0;
+            }
+            if reconnects >= MAX_RECONNECTS || !self.connection_lost(&err).await {
+                return Err(err);
+            }
+
+            reconnects += 1;
+            warn!(
+                "Connection lost ({err}), reconnecting to resume after {} of {} commands (attempt {reconnects}/{MAX_RECONNECTS})",
+                responses.len() - done,
+                cmds.len()
+            );
+            // The link is gone already, this only cleans up the state.
+            let _ = self.disconnect().await;
         }
     }
 
-    pub async fn disconnect(&mut self) -> bluer::Result<()> {
+    fn command_retries(&self) -> u32 {
+        self.config.command_retries
+    }
+
+    fn address(&self) -> Address {
+        self.device.address()
+    }
+
+    fn connect_cache(&self) -> bool {
+        self.config.connect_cache
+    }
+
+    /// Forgets the characteristics found when connecting.
+    fn reset(&mut self) {
         self.read_char = None;
         self.write_char = None;
-        self.device.disconnect().await
     }
 }
 
+/// The meter's commands, implemented on top of any [`Transport`].
+trait MeterCommands: Transport {
+    /// Reads the info of the given section, returning the meter's response
+    /// code if it didn't answer with [`ResponseCode::Ok`].
+    async fn read_section_info(
+        &mut self,
+        section: u8,
+    ) -> error::Result<Result<MeterSectionInfo, ResponseCode>> {
+        let result = self
+            .exec_parsed(
+                &Command::read_section_index_info(section),
+                MeterSectionInfo::from_response,
+            )
+            .await?;
+        match result {
+            Ok(section_info) => {
+                if !section_info.is_consistent() {
+                    warn!("Section info is inconsistent, sample timestamps may be wrong: {section_info}");
+                }
+                Ok(Ok(section_info))
+            }
+            Err(ParseError::BadResponseCode(code)) => Ok(Err(ResponseCode::from_byte(code))),
+            Err(err) => Err(Error::BadResponse(self.address(), err)),
+        }
+    }
+
+    /// Reads the info of every section of the meter's log, indexed by
+    /// section number, returning the meter's response code if it didn't
+    /// answer the first with [`ResponseCode::Ok`].
+    ///
+    /// Sections are read until the meter rejects one, or answers with a
+    /// section it already returned, which is what meters whose log hasn't
+    /// wrapped around do.
+    async fn read_all_sections(
+        &mut self,
+    ) -> error::Result<Result<Vec<MeterSectionInfo>, ResponseCode>> {
+        let mut sections = Vec::new();
+        for section in 0..MAX_SECTIONS {
+            match self.read_section_info(section).await? {
+                Ok(section_info) if !sections.contains(&section_info) => {
+                    sections.push(section_info);
+                }
+                Err(code) if section == 0 => return Ok(Err(code)),
+                _ => break,
+            }
+        }
+        Ok(Ok(sections))
+    }
+
+    /// Reads the samples of `section` with the indices in `range`, paired
+    /// with their Unix timestamps, advancing `progress` with each block read.
+    ///
+    /// Blocks that can't be read or parsed are skipped, so a glitch late in a
+    /// long dump doesn't lose the samples read before it; their number is
+    /// returned along with the samples. With `strict`, failing to read a
+    /// block is an error instead.
+    ///
+    /// Except with [`PairMode::Both`], each value returned stands for a pair
+    /// of samples and carries the timestamp of the first.
+    async fn read_samples(
+        &mut self,
+        section: u8,
+        section_info: &MeterSectionInfo,
+        range: Range<u16>,
+        pair_mode: PairMode,
+        strict: bool,
+        progress: &Progress,
+    ) -> error::Result<(Vec<(i64, Option<MeterSampleValue>)>, usize)> {
+        let mut result = Vec::with_capacity(range.len());
+
+        let blocks = sample_blocks(section_info, &range).collect::<Vec<_>>();
+        let cmds = blocks
+            .iter()
+            .map(|&(i, count)| Command::read_section_samples(section, i, count))
+            .collect::<Vec<_>>();
+        progress.start(cmds.len());
+        let mut responses = Vec::with_capacity(cmds.len());
+        let sent = self.exec_pipelined(&cmds, progress, &mut responses).await;
+        let mut responses = responses.into_iter().map(Some).collect::<Vec<_>>();
+        if let Err(err) = sent {
+            if strict {
+                progress.finish();
+                return Err(err);
+            }
+            warn!("Reading block {} of samples failed: {err}", responses.len());
+            responses.push(None);
+            // Read the remaining blocks one at a time, giving up if the meter
+            // seems to be gone for good.
+            let mut failures = 1;
+            for cmd in &cmds[responses.len()..] {
+                if failures >= MAX_CONSECUTIVE_BLOCK_FAILURES {
+                    break;
+                }
+                match self.exec(cmd).await {
+                    Ok(response) => {
+                        failures = 0;
+                        responses.push(Some(response));
+                    }
+                    Err(err) => {
+                        failures += 1;
+                        warn!("Reading block {} of samples failed: {err}", responses.len());
+                        responses.push(None);
+                    }
+                }
+                progress.inc();
+            }
+        }
+        progress.finish();
+
+        let step = if pair_mode == PairMode::Both { 1 } else { 2 };
+        let mut skipped = cmds.len() - responses.len();
+        for ((cmd, (i, count)), response) in cmds.iter().zip(blocks).zip(responses) {
+            let Some(response) = response else {
+                skipped += 1;
+                continue;
+            };
+            let parsed = self
+                .retry_parsed(cmd, response, |response| {
+                    MeterSampleValue::from_response_with(response, pair_mode)
+                })
+                .await?;
+            match parsed {
+                // Samples come in pairs, so an odd count decodes one too many.
+                Ok(samples) => result.extend(
+                    (i..)
+                        .step_by(step)
+                        .zip(samples.into_iter().take(usize::from(count).div_ceil(step)))
+                        .filter(|(index, _)| range.contains(index))
+                        .map(|(index, sample)| (section_info.timestamp(index), sample)),
+                ),
+                Err(err) => {
+                    warn!("Could not parse samples at index {i}: {err}");
+                    skipped += 1;
+                }
+            }
+        }
+
+        Ok((result, skipped))
+    }
+
+    async fn read_device_info(&mut self) -> error::Result<DeviceInfo> {
+        self.exec_parsed(&Command::read_device_info(), DeviceInfo::from_response)
+            .await?
+            .map_err(|err| Error::BadResponse(self.address(), err))
+    }
+
+    async fn read_alarm_thresholds(&mut self) -> error::Result<AlarmThresholds> {
+        self.exec_parsed(
+            &Command::read_alarm_thresholds(),
+            AlarmThresholds::from_response,
+        )
+        .await?
+        .map_err(|err| Error::BadResponse(self.address(), err))
+    }
+
+    async fn read_name(&mut self) -> error::Result<Option<DeviceName>> {
+        let result = self
+            .exec_parsed(&Command::read_name(), DeviceName::from_response)
+            .await?;
+        match result {
+            Ok(name) => Ok(Some(name)),
+            Err(err) => {
+                warn!("Could not read name: {err}");
+                Ok(None)
+            }
+        }
+    }
+
+    /// Executes a command whose response only consists of a response code.
+    async fn exec_status(&mut self, cmd: &Command) -> error::Result<ResponseCode> {
+        let result = self
+            .exec_parsed(cmd, |response| {
+                match ResponseCode::from_response(response) {
+                    Some(ResponseCode::Ok) => Ok(()),
+                    Some(_) => Err(ParseError::BadResponseCode(response[0])),
+                    None => Err(ParseError::TooShort {
+                        expected: 1,
+                        got: 0,
+                    }),
+                }
+            })
+            .await?;
+        Ok(match result {
+            Ok(()) => ResponseCode::Ok,
+            Err(ParseError::BadResponseCode(code)) => ResponseCode::from_byte(code),
+            Err(_) => ResponseCode::Unknown(0),
+        })
+    }
+
+    /// Executes a command and parses its response, re-issuing the command up
+    /// to `command_retries` times if the response is malformed or not OK.
+    ///
+    /// Commands the meter doesn't support or rejects the parameters of are
+    /// never retried, as they would fail again.
+    async fn exec_parsed<T>(
+        &mut self,
+        cmd: &Command,
+        parse: impl Fn(&[u8]) -> Result<T, ParseError>,
+    ) -> error::Result<Result<T, ParseError>> {
+        let response = self.exec(cmd).await?;
+        self.retry_parsed(cmd, response, parse).await
+    }
+
+    /// Like [`MeterCommands::exec_parsed`], but starts from a `response` to
+    /// `cmd` that has already been received.
+    async fn retry_parsed<T>(
+        &mut self,
+        cmd: &Command,
+        mut response: Vec<u8>,
+        parse: impl Fn(&[u8]) -> Result<T, ParseError>,
+    ) -> error::Result<Result<T, ParseError>> {
+        let mut attempt = 0;
+        loop {
+            let result = parse(&response);
+            let retry = match &result {
+                Ok(_) => false,
+                Err(ParseError::BadResponseCode(code)) => !matches!(
+                    ResponseCode::from_byte(*code),
+                    ResponseCode::Unsupported | ResponseCode::BadParam
+                ),
+                Err(_) => true,
+            };
+            if !retry || attempt >= self.command_retries() {
+                return Ok(result);
+            }
+
+            attempt += 1;
+            if let Err(err) = &result {
+                debug!(
+                    "Bad response ({}), retrying command {} (attempt {}/{})",
+                    err,
+                    cmd.code(),
+                    attempt,
+                    self.command_retries()
+                );
+            }
+            response = self.exec(cmd).await?;
+        }
+    }
+}
+
+impl<T: Transport> MeterCommands for T {}
+
 async fn find_characteristics(
     device: &Device,
 ) -> bluer::Result<Option<(Characteristic, Characteristic)>> {
@@ -176,174 +723,1974 @@ async fn find_characteristics(
     Ok(None)
 }
 
-fn gen_cmd(cmd: u8, payload_length: usize) -> Vec<u8> {
-    let mut data = vec![0u8; 3 + payload_length];
-    data[0] = 0x57;
-    data[1] = if cmd > 0x0f { 0x0f } else { 0 };
-    data[2] = cmd;
-    data
+/// Returns the start index and sample count of the read commands needed to
+/// read the samples in `range`.
+///
+/// Blocks start at multiples of [`SAMPLE_COUNT`]; the last one may be shorter
+/// if the section ends in the middle of a block.
+fn sample_blocks(
+    section_info: &MeterSectionInfo,
+    range: &Range<u16>,
+) -> impl Iterator<Item = (u16, u8)> {
+    let block_len = u16::from(SAMPLE_COUNT);
+    let data_length = section_info.data_length;
+    let first_block = range.start / block_len * block_len;
+    (first_block..range.end.min(data_length))
+        .step_by(SAMPLE_COUNT.into())
+        .map(move |i| {
+            let count = (data_length - i).min(block_len);
+            (i, u8::try_from(count).unwrap())
+        })
+}
+
+/// Prints the raw response to a command to stderr, tagged with the command
+/// and its index among the commands sent together.
+fn print_raw(addr: Address, index: usize, cmd: &Command, response: &[u8]) {
+    eprintln!(
+        "{addr} #{index} {}: {}",
+        hex::encode(cmd.as_bytes()),
+        hex::encode(response)
+    );
+}
+
+/// Writes `cmd` to the meter in a single write.
+///
+/// Every write is a frame of its own to the meter, so the rest of a short
+/// write can't be sent afterwards, and a truncated command is an error.
+async fn write_command(
+    write_io: &mut (impl AsyncWrite + Unpin),
+    cmd: &Command,
+) -> error::Result<()> {
+    let written = write_io.write(cmd.as_bytes()).await?;
+    if written == cmd.as_bytes().len() {
+        Ok(())
+    } else {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::WriteZero,
+            format!(
+                "Wrote only {written} of {} bytes of command {}",
+                cmd.as_bytes().len(),
+                hex::encode(cmd.as_bytes())
+            ),
+        )
+        .into())
+    }
+}
+
+/// Reads the response to `cmd` from the meter's notifications.
+///
+/// The first notification after subscribing is occasionally empty, so an
+/// empty read is retried once before failing with
+/// [`Error::EmptyResponse`]. Notifications that don't fit `cmd` are left over
+/// from another command and are dropped, as are any before a notification
+/// that is a complete response of its own.
+async fn read_response(
+    addr: Address,
+    cmd: &Command,
+    notify_io: &mut (impl AsyncRead + Unpin),
+    buf: &mut [u8],
+    timeout: std::time::Duration,
+) -> error::Result<Vec<u8>> {
+    let mut response = Vec::with_capacity(cmd.response_len().unwrap_or(buf.len()));
+    let mut empty_reads = 0;
+    while !cmd.is_complete(&response) {
+        let read = await_response(addr, timeout, notify_io.read(buf)).await?;
+        if read > 0 {
+            response.extend_from_slice(&buf[..read]);
+            // Only successful responses count, as the later part of a split
+            // response would look like a single status byte error otherwise.
+            let start = response.len() - read;
+            let latest = &response[start..];
+            let complete_on_its_own = ResponseCode::from_response(latest)
+                .is_some_and(ResponseCode::is_ok)
+                && cmd.is_complete(latest)
+                && cmd.fits(latest);
+            if start > 0 && complete_on_its_own {
+                debug!(response = %hex::encode(&response[..start]), "Dropping stale notification");
+                response.drain(..start);
+            } else if cmd.is_complete(&response) && !cmd.fits(&response) {
+                debug!(response = %hex::encode(&response), "Dropping stale notification");
+                // The latest notification may still start the actual response.
+                let latest = response.split_off(response.len() - read);
+                response = if cmd.is_complete(&latest) && !cmd.fits(&latest) {
+                    Vec::new()
+                } else {
+                    latest
+                };
+            }
+        } else if !response.is_empty() {
+            break;
+        } else if empty_reads == 0 {
+            debug!("Empty notification, reading again");
+            empty_reads += 1;
+        } else {
+            return Err(Error::EmptyResponse(addr));
+        }
+    }
+    Ok(response)
+}
+
+/// Waits for the response to a command from the meter at `addr`, failing
+/// with [`Error::NoResponse`] if it doesn't arrive in time.
+async fn await_response<T>(
+    addr: Address,
+    timeout: std::time::Duration,
+    response: impl Future<Output = std::io::Result<T>>,
+) -> error::Result<T> {
+    let Ok(result) = tokio::time::timeout(timeout, response).await else {
+        debug!(%addr, "No response within {timeout:?}");
+        return Err(Error::NoResponse(addr));
+    };
+    Ok(result.map_err(bluer::Error::from)?)
+}
+
+fn backoff_delay(attempt: u32) -> std::time::Duration {
+    let delay = CONNECT_BACKOFF_BASE * 2u32.pow((attempt - 1).min(6));
+    // Cheap jitter of up to half the delay, so several meters being retried
+    // at once don't keep colliding.
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |now| now.subsec_nanos());
+    delay + delay.mul_f64(f64::from(nanos % 1000) / 2000.0)
 }
 
 mod cli {
-    use clap::Parser;
+    use chrono::TimeZone;
+    use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, ValueSource};
+    use meterreader_models::{PairMode, TempUnit};
+    use std::ffi::OsString;
     use std::str::FromStr;
 
+    #[derive(Clone, Copy, Debug, Eq, PartialEq, clap::ValueEnum, serde::Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum OutputFormat {
+        Csv,
+        Json,
+        Influx,
+        Graphite,
+    }
+
+    #[derive(Clone, Copy, Debug, Eq, PartialEq, clap::ValueEnum, serde::Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum TimestampFormat {
+        Local,
+        Utc,
+        Epoch,
+    }
+
+    const EXIT_CODES: &str = "EXIT CODES:
+    0    Success
+    1    Any other error
+    2    Invalid command line or config file
+    3    No usable Bluetooth adapter
+    4    A meter given with --address wasn't found
+    5    Connecting to a meter failed, or it rejected the password
+    6    A meter sent a response that couldn't be used
+    7    A meter didn't respond to a command in time
+    130  Interrupted with Ctrl-C";
+
     #[derive(Debug, Parser)]
+    #[clap(after_help = EXIT_CODES)]
+    #[allow(clippy::struct_excessive_bools)]
     pub struct Args {
+        #[clap(subcommand)]
+        pub action: Action,
+
+        /// Don't show a progress bar while dumping
+        #[clap(long, short, global = true, value_parser)]
+        pub quiet: bool,
+
+        /// Read defaults for some options from the given TOML file
+        #[clap(long, global = true, value_parser)]
+        pub config: Option<std::path::PathBuf>,
+
+        /// Increase logging verbosity, may be repeated
+        #[clap(long, short, global = true, action = clap::ArgAction::Count)]
+        pub verbose: u8,
+
+        /// Only read from devices whose name contains the given string
+        #[clap(long, global = true, value_parser)]
+        pub name: Option<String>,
+
+        /// Name of the Bluetooth adapter to use, e.g. hci1
+        #[clap(long, global = true, value_parser)]
+        pub adapter: Option<String>,
+
+        /// How long to scan for devices [default: 10 seconds]
+        #[clap(long, value_parser=parse_interval)]
+        pub scan_timeout: Option<chrono::Duration>,
+
+        /// How long to wait for a meter to respond to a command [default: 5 seconds]
+        #[clap(long, value_parser=parse_interval)]
+        pub command_timeout: Option<chrono::Duration>,
+
+        /// How often to retry connecting to a meter
+        #[clap(long, default_value_t = 3)]
+        pub connect_retries: u32,
+
+        /// Look up the characteristics of meters again before every command
+        /// instead of once per connection, in case they changed, e.g. after a
+        /// firmware update
         #[clap(long, value_parser)]
-        pub discover: bool,
+        pub no_connect_cache: bool,
 
-        #[clap(long, short, value_parser)]
-        pub dump_historic: bool,
+        /// How often to retry a command if the meter's response is malformed
+        /// or not OK
+        #[clap(long, default_value_t = 1)]
+        pub command_retries: u32,
 
-        #[clap(long, value_parser=parse_duration)]
-        pub dump_last: Option<chrono::Duration>,
+        /// Password of the meters, if one is set in the app
+        #[clap(long, global = true, value_parser)]
+        pub password: Option<String>,
+
+        /// How many meters to read at the same time
+        #[clap(long, default_value_t = 4, value_parser = clap::value_parser!(u16).range(1..))]
+        pub concurrency: u16,
+
+        /// How many commands to send ahead of the response being read while
+        /// dumping; 1 waits for each response before sending the next command
+        #[clap(long, default_value_t = 2, value_parser = clap::value_parser!(u16).range(1..))]
+        pub pipeline_depth: u16,
 
+        /// Warn if the MTU negotiated with a meter is below the given number
+        /// of bytes [default: enough for a block of samples in a single
+        /// notification]
         #[clap(long, value_parser)]
-        pub set_time: bool,
+        pub min_mtu: Option<u16>,
 
-        #[clap(value_parser=parse_addr)]
-        pub address: Option<bluer::Address>,
-    }
+        /// Print temperatures in degrees Fahrenheit instead of Celsius
+        #[clap(long, value_parser)]
+        pub fahrenheit: bool,
 
-    fn parse_addr(s: &str) -> Result<bluer::Address, &'static str> {
-        bluer::Address::from_str(s).map_err(|_| "invalid address")
-    }
+        /// Print timestamps in UTC instead of local time, same as
+        /// `--timestamp-format utc`
+        #[clap(long, value_parser, conflicts_with = "timestamp-format")]
+        pub utc: bool,
 
-    fn parse_duration(s: &str) -> Result<chrono::Duration, &'static str> {
-        let digits: String = s.chars().take_while(char::is_ascii_digit).collect();
-        let mut value = digits.parse::<i64>().map_err(|_| "invalid number")?;
-        let unit: String = s.chars().skip(digits.len()).collect();
-        value *= match unit.as_str() {
-            "m" => 1,
-            "h" => 60,
-            "d" => 60 * 24,
-            _ => return Err("invalid time unit"),
-        };
+        /// How timestamps are printed
+        #[clap(long, value_enum, default_value_t = TimestampFormat::Local)]
+        pub timestamp_format: TimestampFormat,
 
-        Ok(chrono::Duration::minutes(value))
-    }
+        /// Also print the raw response to every command to stderr
+        #[clap(long, value_parser)]
+        pub raw: bool,
 
-    #[cfg(test)]
-    mod tests {
-        use crate::cli::parse_duration;
+        /// Only print the commands set-time, set-unit, clear-history and info
+        /// --set-name would send, without changing anything on the meters
+        #[clap(long, value_parser)]
+        pub dry_run: bool,
 
-        #[test]
-        fn parses_durations() {
-            assert_eq!(parse_duration("1d"), Ok(chrono::Duration::days(1)));
-            assert_eq!(parse_duration("5m"), Ok(chrono::Duration::minutes(5)));
-            assert_eq!(parse_duration("42h"), Ok(chrono::Duration::hours(42)));
-        }
-    }
-}
+        /// Number of decimals temperatures and summary statistics are printed
+        /// with at most
+        #[clap(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(0..=6))]
+        pub precision: u8,
 
-fn dump_csv(index_info: &MeterSectionInfo, samples: &[MeterSampleValue]) {
-    assert!(samples.len() <= u16::MAX.into());
-    let samples_len: u16 = samples.len().try_into().unwrap();
+        /// Add the given number of degrees Celsius to every temperature read,
+        /// to correct meters that read too high or too low
+        #[clap(long, default_value_t = 0.0, allow_hyphen_values = true, value_parser)]
+        pub temp_offset: f32,
 
-    let interval = Duration::seconds(index_info.interval.into());
-    let mut current_time = Local.timestamp(index_info.start_time.into(), 0)
-        + (interval * (index_info.data_length - samples_len).into());
+        /// Add the given number of percentage points to every humidity read
+        #[clap(long, default_value_t = 0, allow_hyphen_values = true, value_parser)]
+        pub humidity_offset: i8,
 
-    for value in samples {
-        println!(
-            "{}\t{}\t{}",
-            current_time, value.temperature, value.humidity
-        );
-        current_time = current_time + interval;
-    }
-}
+        /// Also print the dew point of each reading
+        #[clap(long, value_parser)]
+        pub dew_point: bool,
 
-#[tokio::main(flavor = "current_thread")]
-async fn main() -> bluer::Result<()> {
-    let args = cli::Args::parse();
+        /// Also print the heat index of each reading
+        #[clap(long, value_parser)]
+        pub heat_index: bool,
 
-    let session = bluer::Session::new().await?;
-    let adapter = session.default_adapter().await?;
-    adapter.set_powered(true).await?;
+        /// Also print the absolute humidity of each reading in g/m³
+        #[clap(long, value_parser)]
+        pub absolute_humidity: bool,
 
-    let started = Instant::now();
-    let discover = adapter.discover_devices().await?;
-    pin_mut!(discover);
-    while let Some(evt) = discover.next().await {
-        if let AdapterEvent::DeviceAdded(addr) = evt {
-            if let Some(wanted_addr) = args.address {
-                if addr != wanted_addr {
-                    continue;
-                }
-            }
+        /// Also print whether the temperature and humidity of each historic
+        /// sample look implausible, e.g. because of a corrupted frame, in CSV
+        /// and JSON output
+        #[clap(long, value_parser)]
+        pub sample_flags: bool,
 
-            let device = adapter.device(addr)?;
-            if let Some(service_data) = device.service_data().await? {
-                if let Some(data) = service_data.get(&ADVERTISEMENT_SERVICE_UUID) {
-                    if args.set_time {
-                        let mut meter = Meter::new(&adapter, addr)?;
-                        meter.set_time().await?;
-                        meter.disconnect().await?;
-                    }
+        /// Format used for printing readings
+        #[clap(long, value_enum, default_value_t = OutputFormat::Csv)]
+        pub output: OutputFormat,
 
-                    if args.dump_historic || args.dump_last.is_some() {
-                        let mut meter = Meter::new(&adapter, addr)?;
-                        if let Some(index_info) = meter.read_section_info().await? {
-                            let samples = meter.read_samples(&index_info, args.dump_last).await?;
-                            dump_csv(&index_info, &samples);
-                        }
-                        meter.disconnect().await?;
-                    } else if let Some(value) = MeterValue::from_data(data) {
-                        println!(
-                            "{}: {}°C, {}% humidity, {}% battery",
-                            addr, value.temperature, value.humidity, value.battery
-                        );
-                    }
-                }
-            }
+        /// Indent JSON output over several lines instead of printing each
+        /// object on a single line
+        #[clap(long, value_parser)]
+        pub pretty: bool,
 
-            if args.address.is_some() {
-                break;
-            }
-        }
+        /// Print a header row before CSV data
+        #[clap(long, action = clap::ArgAction::Set, default_value_t = true)]
+        pub header: bool,
 
-        if Instant::now() - started > std::time::Duration::new(10, 0) {
-            break;
-        }
+        /// Character separating the columns of CSV output [default: tab]
+        #[clap(long, default_value_t = '\t', hide_default_value = true, value_parser)]
+        pub delimiter: char,
+
+        /// Print the labels given in the TOML file at the given path, as
+        /// "address" = "label" pairs, instead of the addresses of meters
+        #[clap(long, value_parser)]
+        pub name_map: Option<std::path::PathBuf>,
+
+        /// Write readings to the given file instead of stdout
+        #[clap(long, value_parser)]
+        pub output_file: Option<std::path::PathBuf>,
+
+        /// Write the samples dumped from each meter to <address>.csv, .json or
+        /// .txt, depending on the output format, in the given directory,
+        /// which is created if missing, instead of stdout
+        #[clap(long, value_parser, conflicts_with = "output-file")]
+        pub output_dir: Option<std::path::PathBuf>,
+
+        /// Also store readings in the given sqlite3 database
+        #[cfg(feature = "sqlite")]
+        #[clap(long, value_parser)]
+        pub sqlite: Option<std::path::PathBuf>,
+
+        /// Also write the samples dumped to the given Parquet file
+        #[cfg(feature = "parquet")]
+        #[clap(long, value_parser)]
+        pub parquet: Option<std::path::PathBuf>,
+
+        /// Also publish live readings to the MQTT broker at the given host and
+        /// optional port
+        #[cfg(feature = "mqtt")]
+        #[clap(long, value_parser=crate::mqtt::parse_broker)]
+        pub mqtt: Option<crate::mqtt::Broker>,
+
+        /// Also set the states of sensors for the live readings in the Home
+        /// Assistant instance at the given URL, e.g. `http://homeassistant:8123`
+        #[cfg(feature = "hass")]
+        #[clap(long, value_parser, requires = "hass-token")]
+        pub hass_url: Option<String>,
+
+        /// Long-lived access token used to authenticate with Home Assistant
+        #[cfg(feature = "hass")]
+        #[clap(long, value_parser, requires = "hass-url")]
+        pub hass_token: Option<String>,
+
+        /// Also write the latest live readings to the given file in the
+        /// Prometheus text format after each scan
+        #[clap(long, value_parser)]
+        pub prometheus: Option<std::path::PathBuf>,
+
+        /// Append to the output file instead of truncating it
+        #[clap(long, value_parser, requires = "output-file")]
+        pub append: bool,
+
+        /// Measurement name used for Influx line protocol output
+        #[clap(long, default_value = "meter")]
+        pub measurement: String,
+
+        /// First component of the metric paths of Graphite output
+        #[clap(long, default_value = "meter")]
+        pub prefix: String,
+
+        /// Read the frames of meters from the given file instead of over
+        /// Bluetooth, e.g. to test the output without a meter nearby
+        #[clap(long, global = true, value_parser)]
+        pub replay: Option<std::path::PathBuf>,
+
+        /// Address of a meter to read from; may be given multiple times
+        #[clap(long, short, global = true, value_parser=parse_addr)]
+        pub address: Vec<bluer::Address>,
     }
 
-    Ok(())
-}
+    #[derive(Debug, clap::Subcommand)]
+    pub enum Action {
+        /// Print the current readings the meters advertise
+        Discover {
+            /// Keep scanning and print the live readings at the given interval
+            #[clap(long, value_parser=parse_interval)]
+            watch: Option<chrono::Duration>,
 
-#[cfg(test)]
-mod tests {
-    use crate::{MeterSampleValue, MeterSectionInfo, MeterValue};
+            /// Print the names of the meters along with their addresses
+            #[clap(long, value_parser)]
+            show_name: bool,
 
-    #[test]
-    fn parses_service_data() {
-        let service_data = vec![105, 0, 228, 9, 152, 40];
-        let result = MeterValue::from_data(&service_data);
-        assert_eq!(
-            result,
-            Some(MeterValue {
-                temperature: 24.9,
-                humidity: 40,
-                battery: 100
-            })
-        );
+            /// Only print the battery levels of the meters
+            #[clap(long, value_parser)]
+            battery_only: bool,
+
+            /// Only print meters whose battery level is at or below the given
+            /// percentage
+            #[clap(long, value_parser = clap::value_parser!(u8).range(0..=100))]
+            min_battery: Option<u8>,
+
+            /// While watching, print unchanged readings again after the given
+            /// interval instead of only when they change
+            #[clap(long, value_parser=parse_interval, requires = "watch")]
+            min_interval: Option<chrono::Duration>,
+
+            /// While watching, also serve the live readings to Prometheus on
+            /// /metrics at the given address, e.g. 0.0.0.0:9100
+            #[cfg(feature = "serve")]
+            #[clap(long, value_parser, requires = "watch")]
+            serve: Option<std::net::SocketAddr>,
+        },
+        /// Dump the historic data stored on the meters
+        Dump(DumpArgs),
+        /// Set the clocks of the meters to the current time
+        SetTime,
+        /// Set the unit the displays of the meters show temperatures in
+        SetUnit {
+            /// "c" for Celsius or "f" for Fahrenheit
+            #[clap(value_parser=parse_unit)]
+            unit: TempUnit,
+        },
+        /// Delete all historic data stored on the meters given with --address,
+        /// e.g. to test dumping from a clean slate; the data is lost for good
+        ClearHistory {
+            /// Confirm deleting the history, which is required
+            #[clap(long, required = true, value_parser)]
+            yes: bool,
+        },
+        /// Print the firmware and hardware version and the names of the meters
+        Info {
+            /// Set the name of the meters before printing their info
+            #[clap(long, value_parser=parse_name)]
+            set_name: Option<String>,
+        },
+        /// Print the thresholds of the temperature and humidity alarms of the
+        /// meters
+        Alarms,
     }
 
-    #[test]
-    fn parses_sample_info() {
-        let response = vec![1, 152, 40, 119, 152, 40, 152, 40, 120, 152, 40];
-        let result = MeterSampleValue::from_response(&response);
+    #[derive(Debug, clap::Args)]
+    #[allow(clippy::struct_excessive_bools)]
+    pub struct DumpArgs {
+        /// Only dump the given duration of most recent data, e.g. 12h or 1d12h
+        #[clap(long, value_parser=parse_duration)]
+        pub last: Option<chrono::Duration>,
+
+        /// Dump the data recorded at or after the given local time, e.g.
+        /// "2022-06-01 14:00"
+        #[clap(long, value_parser=parse_datetime, conflicts_with = "last")]
+        pub since: Option<chrono::DateTime<chrono::Local>>,
+
+        /// Dump the data recorded at or before the given local time
+        #[clap(long, value_parser=parse_datetime, conflicts_with = "last")]
+        pub until: Option<chrono::DateTime<chrono::Local>>,
+
+        /// Only dump the newest N of the selected samples
+        #[clap(long, value_parser)]
+        pub limit: Option<u16>,
+
+        /// Average the data over buckets of the given duration
+        #[clap(long, value_parser=parse_interval)]
+        pub bucket: Option<chrono::Duration>,
+
+        /// Which of the two samples the meters store together to dump:
+        /// "both", only the "first", or their "average"
+        #[clap(long, value_parser=parse_pair_mode, default_value = "both")]
+        pub pair_mode: PairMode,
+
+        /// Leave out samples from gaps in the meters' logs instead of
+        /// printing them without values
+        #[clap(long, value_parser)]
+        pub skip_gaps: bool,
+
+        /// Print summary statistics of the data instead of the individual
+        /// samples
+        #[clap(long, value_parser)]
+        pub summary: bool,
+
+        /// Also dump the older sections of the logs of meters whose log has
+        /// wrapped around, merging the samples of all sections
+        #[clap(long, value_parser)]
+        pub all_sections: bool,
+
+        /// Skip meters whose section info is inconsistent, and stop at the
+        /// first block of samples that can't be read, instead of only warning
+        /// about them
+        #[clap(long, value_parser)]
+        pub strict: bool,
+    }
+
+    /// Defaults for options that weren't given on the command line, read from
+    /// the file given with `--config`.
+    #[derive(Debug, Default, serde::Deserialize)]
+    #[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+    pub struct Config {
+        adapter: Option<String>,
+        address: Vec<String>,
+        output: Option<OutputFormat>,
+        scan_timeout: Option<String>,
+        timestamp_format: Option<TimestampFormat>,
+    }
+
+    impl Args {
+        /// Parses the command line, exiting with a usage error if it or the
+        /// config file is invalid.
+        pub fn load() -> Args {
+            Args::try_load_from(std::env::args_os()).unwrap_or_else(|err| err.exit())
+        }
+
+        fn try_load_from(
+            args: impl IntoIterator<Item = impl Into<OsString> + Clone>,
+        ) -> clap::Result<Args> {
+            let matches = Args::command().try_get_matches_from(args)?;
+            let mut args = Args::from_arg_matches(&matches)?;
+            if let Some(path) = args.config.clone() {
+                let config = std::fs::read_to_string(&path)
+                    .map_err(|err| err.to_string())
+                    .and_then(|config| toml::from_str(&config).map_err(|err| err.to_string()))
+                    .map_err(|err| {
+                        Args::command().error(
+                            clap::ErrorKind::Io,
+                            format!("Could not read config file {}: {err}", path.display()),
+                        )
+                    })?;
+                args.apply(config, &matches).map_err(|err| {
+                    Args::command().error(
+                        clap::ErrorKind::InvalidValue,
+                        format!("Invalid value in config file {}: {err}", path.display()),
+                    )
+                })?;
+            }
+            // Without an address, every meter in range would lose its history.
+            if matches!(args.action, Action::ClearHistory { .. }) && args.address.is_empty() {
+                return Err(Args::command().error(
+                    clap::ErrorKind::MissingRequiredArgument,
+                    "clear-history needs at least one --address",
+                ));
+            }
+            Ok(args)
+        }
+
+        /// Takes the options from `config` that weren't given on the command
+        /// line.
+        fn apply(&mut self, config: Config, matches: &ArgMatches) -> Result<(), &'static str> {
+            let unset = |id| matches.value_source(id) != Some(ValueSource::CommandLine);
+
+            if unset("adapter") && config.adapter.is_some() {
+                self.adapter = config.adapter;
+            }
+            if unset("address") && !config.address.is_empty() {
+                self.address = config
+                    .address
+                    .iter()
+                    .map(|addr| parse_addr(addr))
+                    .collect::<Result<_, _>>()?;
+            }
+            if let Some(output) = config.output.filter(|_| unset("output")) {
+                self.output = output;
+            }
+            if let Some(scan_timeout) = config.scan_timeout.filter(|_| unset("scan-timeout")) {
+                self.scan_timeout = Some(parse_interval(&scan_timeout)?);
+            }
+            // `--utc` is a shorthand for the timestamp format.
+            if let Some(format) = config
+                .timestamp_format
+                .filter(|_| unset("timestamp-format") && !self.utc)
+            {
+                self.timestamp_format = format;
+            }
+            Ok(())
+        }
+
+        pub fn wants(&self, addr: bluer::Address) -> bool {
+            self.address.is_empty() || self.address.contains(&addr)
+        }
+    }
+
+    fn parse_addr(s: &str) -> Result<bluer::Address, &'static str> {
+        bluer::Address::from_str(s).map_err(|_| "invalid address")
+    }
+
+    fn parse_unit(s: &str) -> Result<TempUnit, &'static str> {
+        match s.to_ascii_lowercase().as_str() {
+            "c" | "celsius" => Ok(TempUnit::Celsius),
+            "f" | "fahrenheit" => Ok(TempUnit::Fahrenheit),
+            _ => Err("expected c or f"),
+        }
+    }
+
+    fn parse_pair_mode(s: &str) -> Result<PairMode, &'static str> {
+        match s.to_ascii_lowercase().as_str() {
+            "both" => Ok(PairMode::Both),
+            "first" => Ok(PairMode::First),
+            "average" => Ok(PairMode::Average),
+            _ => Err("expected both, first or average"),
+        }
+    }
+
+    fn parse_name(s: &str) -> Result<String, String> {
+        let max_len = meterreader_models::Command::MAX_NAME_LEN;
+        if s.len() > max_len {
+            Err(format!("must be at most {max_len} bytes long"))
+        } else {
+            Ok(s.to_string())
+        }
+    }
+
+    /// Parses a duration made up of one or more `<number><unit>` segments,
+    /// e.g. 1d12h, with the units s, m, h, d and w.
+    fn parse_duration(s: &str) -> Result<chrono::Duration, &'static str> {
+        if s.is_empty() {
+            return Err("empty duration");
+        }
+
+        let mut seconds = 0i64;
+        let mut rest = s;
+        while !rest.is_empty() {
+            let digits = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let value = rest[..digits]
+                .parse::<i64>()
+                .map_err(|_| "invalid number")?;
+            let mut chars = rest[digits..].chars();
+            let unit = match chars.next() {
+                Some('s') => 1,
+                Some('m') => 60,
+                Some('h') => 60 * 60,
+                Some('d') => 60 * 60 * 24,
+                Some('w') => 60 * 60 * 24 * 7,
+                Some(_) => return Err("invalid time unit"),
+                None => return Err("missing time unit"),
+            };
+            seconds = value
+                .checked_mul(unit)
+                .and_then(|segment| seconds.checked_add(segment))
+                .ok_or("duration too long")?;
+            rest = chars.as_str();
+        }
+
+        // chrono durations are limited to i64::MAX milliseconds.
+        if seconds > i64::MAX / 1000 {
+            return Err("duration too long");
+        }
+        Ok(chrono::Duration::seconds(seconds))
+    }
+
+    fn parse_datetime(s: &str) -> Result<chrono::DateTime<chrono::Local>, &'static str> {
+        if let Ok(datetime) = chrono::DateTime::parse_from_rfc3339(s) {
+            return Ok(datetime.with_timezone(&chrono::Local));
+        }
+        let naive = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"]
+            .iter()
+            .find_map(|format| chrono::NaiveDateTime::parse_from_str(s, format).ok())
+            .or_else(|| {
+                chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                    .ok()
+                    .and_then(|date| date.and_hms_opt(0, 0, 0))
+            })
+            .ok_or("invalid date and time")?;
+        chrono::Local
+            .from_local_datetime(&naive)
+            .earliest()
+            .ok_or("nonexistent local time")
+    }
+
+    fn parse_interval(s: &str) -> Result<chrono::Duration, &'static str> {
+        let duration = parse_duration(s)?;
+        if duration.is_zero() {
+            return Err("interval must not be zero");
+        }
+        Ok(duration)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use crate::cli::{
+            parse_datetime, parse_duration, parse_interval, parse_name, parse_unit, Action, Args,
+            Config, OutputFormat, TimestampFormat,
+        };
+        use chrono::TimeZone;
+        use clap::{CommandFactory, FromArgMatches, Parser};
+        use meterreader_models::TempUnit;
+
+        #[test]
+        fn parses_subcommands() {
+            Args::command().debug_assert();
+
+            let args = Args::try_parse_from([
+                "meterreader",
+                "dump",
+                "--last",
+                "12h",
+                "-a",
+                "D3:4E:07:01:02:03",
+            ])
+            .unwrap();
+            assert_eq!(args.address.len(), 1);
+            match args.action {
+                Action::Dump(dump) => assert_eq!(dump.last, Some(chrono::Duration::hours(12))),
+                action => panic!("unexpected action {action:?}"),
+            }
+
+            assert!(Args::try_parse_from(["meterreader"]).is_err());
+            assert!(Args::try_parse_from(["meterreader", "set-time", "--last", "1h"]).is_err());
+            assert!(
+                Args::try_parse_from(["meterreader", "discover", "--min-battery", "101"]).is_err()
+            );
+            assert!(Args::try_parse_from(["meterreader", "clear-history"]).is_err());
+            assert!(Args::try_load_from(["meterreader", "clear-history", "--yes"]).is_err());
+            assert!(Args::try_load_from([
+                "meterreader",
+                "clear-history",
+                "--yes",
+                "-a",
+                "D3:4E:07:01:02:03"
+            ])
+            .is_ok());
+        }
+
+        #[test]
+        fn parses_durations() {
+            assert_eq!(parse_duration("1d"), Ok(chrono::Duration::days(1)));
+            assert_eq!(parse_duration("5m"), Ok(chrono::Duration::minutes(5)));
+            assert_eq!(parse_duration("42h"), Ok(chrono::Duration::hours(42)));
+            assert_eq!(parse_duration("30s"), Ok(chrono::Duration::seconds(30)));
+            assert_eq!(parse_duration("150s"), Ok(chrono::Duration::seconds(150)));
+            assert_eq!(parse_duration("2m30s"), Ok(chrono::Duration::seconds(150)));
+            assert_eq!(parse_duration("90m"), Ok(chrono::Duration::minutes(90)));
+            assert_eq!(parse_duration("1d12h"), Ok(chrono::Duration::hours(36)));
+            assert_eq!(parse_duration("1w2d"), Ok(chrono::Duration::days(9)));
+
+            assert_eq!(parse_duration(""), Err("empty duration"));
+            assert_eq!(parse_duration("12"), Err("missing time unit"));
+            assert_eq!(parse_duration("1d12"), Err("missing time unit"));
+            assert_eq!(parse_duration("1dx"), Err("invalid number"));
+            assert_eq!(parse_duration("5y"), Err("invalid time unit"));
+            assert_eq!(parse_duration("h"), Err("invalid number"));
+            assert!(parse_duration("9999999999999999w").is_err());
+        }
+
+        #[test]
+        fn parses_datetimes() {
+            assert_eq!(
+                parse_datetime("2022-06-01T14:00:00Z").map(|datetime| datetime.timestamp()),
+                Ok(1_654_092_000)
+            );
+            let local = chrono::Local
+                .with_ymd_and_hms(2022, 6, 1, 14, 0, 0)
+                .unwrap();
+            assert_eq!(parse_datetime("2022-06-01 14:00"), Ok(local));
+            assert_eq!(parse_datetime("2022-06-01 14:00:00"), Ok(local));
+            assert!(parse_datetime("yesterday").is_err());
+        }
+
+        #[test]
+        fn rejects_zero_intervals() {
+            assert_eq!(parse_interval("5m"), Ok(chrono::Duration::minutes(5)));
+            assert!(parse_interval("0m").is_err());
+        }
+
+        #[test]
+        fn parses_units() {
+            assert_eq!(parse_unit("c"), Ok(TempUnit::Celsius));
+            assert_eq!(parse_unit("F"), Ok(TempUnit::Fahrenheit));
+            assert!(parse_unit("k").is_err());
+        }
+
+        #[test]
+        fn rejects_long_names() {
+            assert_eq!(parse_name("Garage"), Ok("Garage".to_string()));
+            assert_eq!(
+                parse_name("A name much too long for a meter"),
+                Err("must be at most 20 bytes long".to_string())
+            );
+        }
+
+        #[test]
+        fn applies_config_defaults() {
+            let config: Config = toml::from_str(
+                r#"
+                adapter = "hci1"
+                address = ["D3:4E:07:01:02:03"]
+                output = "json"
+                scan-timeout = "2m"
+                timestamp-format = "epoch"
+                "#,
+            )
+            .unwrap();
+            let matches = Args::command()
+                .try_get_matches_from(["meterreader", "--output", "influx", "discover"])
+                .unwrap();
+            let mut args = Args::from_arg_matches(&matches).unwrap();
+            args.apply(config, &matches).unwrap();
+
+            assert_eq!(args.adapter.as_deref(), Some("hci1"));
+            assert_eq!(args.address.len(), 1);
+            assert_eq!(args.output, OutputFormat::Influx);
+            assert_eq!(args.scan_timeout, Some(chrono::Duration::minutes(2)));
+            assert_eq!(args.timestamp_format, TimestampFormat::Epoch);
+
+            assert!(toml::from_str::<Config>("output = \"xml\"").is_err());
+            assert!(toml::from_str::<Config>("verbose = 3").is_err());
+        }
+    }
+}
+
+async fn read_value(device: &Device) -> bluer::Result<Option<MeterValue>> {
+    let manufacturer_data = read_manufacturer_data(device).await?;
+    Ok(device.service_data().await?.and_then(|service_data| {
+        service_data
+            .get(&ADVERTISEMENT_SERVICE_UUID)
+            .and_then(|data| {
+                MeterValue::from_advertisement(data, manufacturer_data.as_deref()).ok()
+            })
+    }))
+}
+
+/// Reads the manufacturer data the Outdoor Meter advertises its readings in.
+async fn read_manufacturer_data(device: &Device) -> bluer::Result<Option<Vec<u8>>> {
+    Ok(device
+        .manufacturer_data()
+        .await?
+        .and_then(|mut manufacturer_data| manufacturer_data.remove(&MANUFACTURER_ID)))
+}
+
+async fn matches_name(args: &cli::Args, device: &Device) -> bluer::Result<bool> {
+    Ok(match &args.name {
+        Some(wanted) => device
+            .name()
+            .await?
+            .is_some_and(|name| name.contains(wanted.as_str())),
+        None => true,
+    })
+}
+
+/// Warns if the battery of a meter needs replacing soon.
+fn warn_on_low_battery(addr: Address, value: &MeterValue) {
+    let state = value.battery_state();
+    if matches!(state, BatteryState::Low | BatteryState::Critical) {
+        warn!(%addr, "Battery is {state} ({}%)", value.battery);
+    }
+}
+
+/// Returns the name of the device if it should be printed with its readings.
+async fn shown_name(args: &cli::Args, device: &Device) -> bluer::Result<Option<String>> {
+    match args.action {
+        cli::Action::Discover {
+            show_name: true, ..
+        } => device.name().await,
+        _ => Ok(None),
+    }
+}
+
+/// Tracks the last reading printed per meter, so that meters re-advertising
+/// the same values don't flood the output.
+struct Dedup {
+    min_interval: Option<std::time::Duration>,
+    last: BTreeMap<Address, ((Celsius, Humidity, u8), Instant)>,
+}
+
+impl Dedup {
+    fn new(min_interval: Option<std::time::Duration>) -> Dedup {
+        Dedup {
+            min_interval,
+            last: BTreeMap::new(),
+        }
+    }
+
+    /// Returns whether `value` should be printed, i.e. it differs from the
+    /// last reading printed for the meter or that was at least
+    /// `min_interval` before `now`.
+    fn should_print(&mut self, addr: Address, value: &MeterValue, now: Instant) -> bool {
+        let reading = (value.temperature, value.humidity, value.battery);
+        if let Some((last, printed)) = self.last.get(&addr) {
+            let due = self
+                .min_interval
+                .is_some_and(|min_interval| now.duration_since(*printed) >= min_interval);
+            if *last == reading && !due {
+                return false;
+            }
+        }
+        self.last.insert(addr, (reading, now));
+        true
+    }
+}
+
+async fn watch(
+    adapter: &Adapter,
+    args: &cli::Args,
+    printer: &mut output::Printer,
+    interval: Duration,
+    min_interval: Option<Duration>,
+) -> error::Result<()> {
+    let mut devices = BTreeSet::new();
+    let mut dedup = Dedup::new(min_interval.map(|min_interval| min_interval.to_std().unwrap()));
+
+    let discover = adapter.discover_devices().await?;
+    pin_mut!(discover);
+    let mut ticker = tokio::time::interval(interval.to_std().unwrap());
+    let ctrl_c = tokio::signal::ctrl_c();
+    pin_mut!(ctrl_c);
+    loop {
+        tokio::select! {
+            Some(evt) = discover.next() => match evt {
+                AdapterEvent::DeviceAdded(addr) => {
+                    if args.wants(addr) && matches_name(args, &adapter.device(addr)?).await? {
+                        devices.insert(addr);
+                    }
+                }
+                AdapterEvent::DeviceRemoved(addr) => {
+                    devices.remove(&addr);
+                }
+                AdapterEvent::PropertyChanged(_) => {}
+            },
+            _ = ticker.tick() => {
+                for addr in &devices {
+                    // The device might have vanished since the last tick.
+                    let device = adapter.device(*addr)?;
+                    if let Ok(Some(value)) = read_value(&device).await {
+                        if !dedup.should_print(*addr, &value, Instant::now()) {
+                            continue;
+                        }
+                        let name = shown_name(args, &device).await.unwrap_or_default();
+                        let rssi = device.rssi().await.unwrap_or_default();
+                        warn_on_low_battery(*addr, &value);
+                        printer.print_value(*addr, name.as_deref(), &value, rssi)?;
+                    }
+                }
+                printer.end_scan()?;
+            },
+            _ = &mut ctrl_c => break,
+        }
+    }
+
+    Ok(())
+}
+
+async fn open_adapter(session: &bluer::Session, args: &cli::Args) -> error::Result<Adapter> {
+    if let Some(name) = &args.adapter {
+        let names = session.adapter_names().await?;
+        if !names.contains(name) {
+            return Err(Error::NoAdapter(format!(
+                "Bluetooth adapter {name} not found, available adapters: {}",
+                names.join(", ")
+            )));
+        }
+        Ok(session.adapter(name)?)
+    } else {
+        match session.adapter_names().await {
+            Ok(names) if names.is_empty() => Err(Error::NoAdapter(
+                "No Bluetooth adapter found; is Bluetooth enabled?".to_string(),
+            )),
+            Ok(_) => Ok(session.default_adapter().await?),
+            Err(err) => Err(Error::NoAdapter(format!(
+                "No Bluetooth adapter found ({err}); is the service running and is Bluetooth enabled?"
+            ))),
+        }
+    }
+}
+
+/// Runs the requested action on a meter.
+async fn handle_meter(
+    adapter: &Adapter,
+    addr: Address,
+    data: &[u8],
+    manufacturer_data: Option<&[u8]>,
+    args: &cli::Args,
+    config: &MeterConfig,
+    printer: &RefCell<output::Printer>,
+) -> error::Result<()> {
+    if let cli::Action::Discover { .. } = args.action {
+        if let Ok(value) = MeterValue::from_advertisement(data, manufacturer_data) {
+            let device = adapter.device(addr)?;
+            let name = shown_name(args, &device).await?;
+            // Not every advertisement carries the signal strength.
+            let rssi = device.rssi().await?;
+            warn_on_low_battery(addr, &value);
+            printer
+                .borrow_mut()
+                .print_value(addr, name.as_deref(), &value, rssi)?;
+        }
+        return Ok(());
+    }
+
+    let mut meter = Meter::new(adapter, addr, config)?;
+    let result = run_command(&mut meter, addr, args, printer).await;
+    // Disconnect even if the command failed part way.
+    if meter.read_char.is_some() {
+        meter.disconnect().await?;
+    }
+    result
+}
+
+/// Runs the command given on the command line, other than discovering, over
+/// `meter`.
+async fn run_command(
+    meter: &mut impl MeterCommands,
+    addr: Address,
+    args: &cli::Args,
+    printer: &RefCell<output::Printer>,
+) -> error::Result<()> {
+    match &args.action {
+        cli::Action::Discover { .. } => {}
+        cli::Action::Dump(dump) => {
+            let sections = if dump.all_sections {
+                meter.read_all_sections().await?
+            } else {
+                meter
+                    .read_section_info(0)
+                    .await?
+                    .map(|section_info| vec![section_info])
+            };
+            match sections {
+                Ok(sections)
+                    if dump.strict && !sections.iter().all(MeterSectionInfo::is_consistent) =>
+                {
+                    return Err(Error::InconsistentSectionInfo(addr));
+                }
+                Ok(sections) if sections.is_empty() => {}
+                Ok(sections) => {
+                    let mut samples = dump_samples(meter, addr, args, dump, &sections).await?;
+                    if dump.skip_gaps {
+                        samples.retain(|(_, sample)| sample.is_some());
+                    }
+                    if let Some(bucket) = dump.bucket {
+                        samples =
+                            stats::bucket(&stats::without_gaps(&samples), bucket.num_seconds())
+                                .into_iter()
+                                .map(|(timestamp, sample)| (timestamp, Some(sample)))
+                                .collect();
+                    }
+                    if dump.summary {
+                        printer
+                            .borrow_mut()
+                            .print_summary(addr, &stats::without_gaps(&samples))?;
+                    } else {
+                        printer.borrow_mut().print_samples(addr, &samples)?;
+                    }
+                }
+                Err(code) => warn!(%addr, "Could not read section info: {code}"),
+            }
+        }
+        cli::Action::SetTime => {
+            let cmd = Command::set_time(Local::now().timestamp());
+            change_setting(meter, addr, args, &cmd, "set time").await?;
+        }
+        cli::Action::SetUnit { unit } => {
+            let cmd = Command::set_display_unit(*unit);
+            change_setting(meter, addr, args, &cmd, "set display unit").await?;
+        }
+        cli::Action::ClearHistory { .. } => {
+            let cmd = Command::clear_history();
+            change_setting(meter, addr, args, &cmd, "clear history").await?;
+        }
+        cli::Action::Info { set_name } => {
+            if let Some(name) = set_name {
+                let cmd = Command::set_name(name).ok_or(Error::NameTooLong(name.len()))?;
+                change_setting(meter, addr, args, &cmd, "set name").await?;
+            }
+            let device_info = meter.read_device_info().await?;
+            let name = meter.read_name().await?;
+            printer
+                .borrow_mut()
+                .print_device_info(addr, &device_info, name.as_ref())?;
+        }
+        cli::Action::Alarms => {
+            let thresholds = meter.read_alarm_thresholds().await?;
+            printer
+                .borrow_mut()
+                .print_alarm_thresholds(addr, &thresholds)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the samples selected by the options of `dump` from the given
+/// sections of the meter's log, indexed by section number.
+///
+/// Samples of several sections are merged in order of their timestamps,
+/// dropping those recorded in more than one.
+async fn dump_samples(
+    meter: &mut impl MeterCommands,
+    addr: Address,
+    args: &cli::Args,
+    dump: &cli::DumpArgs,
+    sections: &[MeterSectionInfo],
+) -> error::Result<Vec<(i64, Option<MeterSampleValue>)>> {
+    let newest = sections
+        .iter()
+        .map(|section_info| section_info.timestamp(section_info.data_length.saturating_sub(1)))
+        .max()
+        .unwrap_or_default();
+
+    let mut samples = Vec::new();
+    let mut skipped = 0;
+    for (section, section_info) in (0..).zip(sections) {
+        let range = dump_range(section_info, dump, newest);
+        if range.is_empty() {
+            continue;
+        }
+        let (section_samples, section_skipped) = meter
+            .read_samples(
+                section,
+                section_info,
+                range,
+                dump.pair_mode,
+                dump.strict,
+                &Progress::new(&addr.to_string(), !args.quiet),
+            )
+            .await?;
+        samples.extend(section_samples);
+        skipped += section_skipped;
+    }
+    if skipped > 0 {
+        warn!(%addr, "Skipped {skipped} unreadable blocks of samples");
+    }
+
+    if sections.len() > 1 {
+        merge_sections(&mut samples);
+        if let Some(limit) = dump.limit {
+            samples.drain(..samples.len().saturating_sub(limit.into()));
+        }
+    }
+    Ok(samples)
+}
+
+/// Returns the indices of the samples of a section selected by the options of
+/// `dump`, where `newest` is the Unix timestamp of the newest sample of all
+/// sections read.
+fn dump_range(section_info: &MeterSectionInfo, dump: &cli::DumpArgs, newest: i64) -> Range<u16> {
+    let last = section_info.timestamp(section_info.data_length.saturating_sub(1));
+    let mut range = match dump.last {
+        Some(duration) if last >= newest => section_info.last_samples(duration.num_seconds()),
+        // Older sections only add what was recorded within the duration
+        // before the newest sample.
+        Some(duration) => section_info.sample_range(Some(newest - duration.num_seconds()), None),
+        None => section_info.sample_range(
+            dump.since.map(|since| since.timestamp()),
+            dump.until.map(|until| until.timestamp()),
+        ),
+    };
+    if let Some(limit) = dump.limit {
+        range.start = range.start.max(range.end.saturating_sub(limit));
+    }
+    range
+}
+
+/// Sorts the samples of several sections by their timestamps and drops the
+/// duplicates of overlapping sections, keeping samples with data over gaps.
+fn merge_sections(samples: &mut Vec<(i64, Option<MeterSampleValue>)>) {
+    samples.sort_by_key(|(timestamp, sample)| (*timestamp, sample.is_none()));
+    samples.dedup_by_key(|(timestamp, _)| *timestamp);
+}
+
+/// Sends a command changing a setting of the meter, described by `what`, or
+/// with `--dry-run` only prints the frame that would be sent.
+async fn change_setting(
+    meter: &mut impl MeterCommands,
+    addr: Address,
+    args: &cli::Args,
+    cmd: &Command,
+    what: &str,
+) -> error::Result<()> {
+    if args.dry_run {
+        let frame = match &args.password {
+            Some(password) => cmd.clone().with_password(password.as_bytes()),
+            None => cmd.clone(),
+        };
+        println!(
+            "{addr}: dry run, would {what} with {}, nothing was changed",
+            hex::encode(frame.as_bytes())
+        );
+        return Ok(());
+    }
+
+    let code = meter.exec_status(cmd).await?;
+    if !code.is_ok() {
+        warn!(%addr, "Could not {what}: {code}");
+    }
+    Ok(())
+}
+
+/// Discovers meters and handles up to `--concurrency` of them at once.
+///
+/// Each meter's output is printed in one go once it has been read, so the
+/// output of meters handled at the same time doesn't get interleaved.
+async fn scan(
+    adapter: &Adapter,
+    args: &cli::Args,
+    config: &MeterConfig,
+    printer: &RefCell<output::Printer>,
+) -> error::Result<()> {
+    let scan_timeout = args.scan_timeout.unwrap_or_else(|| Duration::seconds(10));
+    let deadline = tokio::time::Instant::now() + scan_timeout.to_std().unwrap();
+
+    let mut handled = BTreeSet::new();
+    let mut failed = Vec::new();
+    let mut pending = FuturesUnordered::new();
+    let mut discovering = true;
+    let discover = adapter.discover_devices().await?;
+    pin_mut!(discover);
+    while discovering || !pending.is_empty() {
+        tokio::select! {
+            evt = tokio::time::timeout_at(deadline, discover.next()),
+                if discovering && pending.len() < args.concurrency.into() =>
+            {
+                let Ok(Some(evt)) = evt else {
+                    discovering = false;
+                    continue;
+                };
+                let AdapterEvent::DeviceAdded(addr) = evt else {
+                    continue;
+                };
+                if !args.wants(addr) || handled.contains(&addr) {
+                    continue;
+                }
+
+                // Failing to look at one device mustn't abort handling the
+                // meters already pending.
+                let advertisement = async {
+                    let device = adapter.device(addr)?;
+                    if !matches_name(args, &device).await? {
+                        return Ok(None);
+                    }
+                    let data = device.service_data().await?.and_then(|mut service_data| {
+                        service_data.remove(&ADVERTISEMENT_SERVICE_UUID)
+                    });
+                    let manufacturer_data = match data {
+                        Some(_) => read_manufacturer_data(&device).await?,
+                        None => None,
+                    };
+                    Ok::<_, bluer::Error>(Some((data, manufacturer_data)))
+                };
+                let (data, manufacturer_data) = match advertisement.await {
+                    Ok(Some(advertisement)) => advertisement,
+                    Ok(None) => continue,
+                    Err(err) => {
+                        warn!(%addr, "Could not read advertisement: {err}");
+                        continue;
+                    }
+                };
+                if let Some(data) = data {
+                    pending.push(async move {
+                        let result = handle_meter(
+                            adapter,
+                            addr,
+                            &data,
+                            manufacturer_data.as_deref(),
+                            args,
+                            config,
+                            printer,
+                        )
+                        .await;
+                        (addr, result)
+                    });
+                }
+
+                handled.insert(addr);
+                if !args.address.is_empty() && args.address.iter().all(|a| handled.contains(a)) {
+                    discovering = false;
+                }
+            }
+            Some((addr, result)) = pending.next() => {
+                // Each meter disconnects itself when handling it fails, so
+                // this keeps going with the others.
+                if let Err(err) = result {
+                    error!(%addr, "{err}");
+                    failed.push(err.exit_code());
+                }
+            }
+        }
+    }
+
+    let missing = args
+        .address
+        .iter()
+        .filter(|addr| !handled.contains(*addr))
+        .copied()
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        return Err(Error::MetersNotFound(missing));
+    }
+    match failed.first() {
+        Some(&exit_code) => Err(Error::MetersFailed {
+            count: failed.len(),
+            exit_code,
+        }),
+        None => Ok(()),
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> std::process::ExitCode {
+    let args = cli::Args::load();
+    tracing_subscriber::fmt()
+        .with_max_level(match args.verbose {
+            0 => tracing::Level::WARN,
+            1 => tracing::Level::INFO,
+            2 => tracing::Level::DEBUG,
+            _ => tracing::Level::TRACE,
+        })
+        .with_writer(std::io::stderr)
+        .init();
+
+    match try_main(&args).await {
+        Ok(()) => std::process::ExitCode::SUCCESS,
+        Err(err) => {
+            error!("{err}");
+            err.exit_code().into()
+        }
+    }
+}
+
+/// Runs the action given on the command line.
+async fn try_main(args: &cli::Args) -> error::Result<()> {
+    let mut printer = RefCell::new(output::Printer::new(args)?);
+    let config = MeterConfig::new(args);
+
+    let result = if let Some(path) = &args.replay {
+        replay::run(path, args, &printer).await
+    } else {
+        let session = bluer::Session::new().await?;
+        let adapter = open_adapter(&session, args).await?;
+        adapter.set_powered(true).await?;
+
+        if let cli::Action::Discover {
+            watch: Some(interval),
+            min_interval,
+            ..
+        } = args.action
+        {
+            #[cfg(feature = "serve")]
+            if let cli::Action::Discover {
+                serve: Some(addr), ..
+            } = args.action
+            {
+                let listener = tokio::net::TcpListener::bind(addr).await?;
+                let gauges = printer.get_mut().serve_gauges();
+                info!("Serving metrics on http://{addr}/metrics");
+                tokio::spawn(serve::serve(listener, gauges));
+            }
+            watch(&adapter, args, printer.get_mut(), interval, min_interval).await
+        } else {
+            run(&adapter, args, &config, &printer).await
+        }
+    };
+    printer.get_mut().finish().await?;
+    result
+}
+
+async fn run(
+    adapter: &Adapter,
+    args: &cli::Args,
+    config: &MeterConfig,
+    printer: &RefCell<output::Printer>,
+) -> error::Result<()> {
+    // Dropping the scan future on interrupt cancels any running command at
+    // its next await point, so only the connections have to be cleaned up.
+    tokio::select! {
+        result = scan(adapter, args, config, printer) => result,
+        _ = tokio::signal::ctrl_c() => {
+            warn!("Interrupted, disconnecting");
+            let connected = std::mem::take(&mut *config.connected.borrow_mut());
+            for addr in connected {
+                let result = match adapter.device(addr) {
+                    Ok(device) => device.disconnect().await,
+                    Err(err) => Err(err),
+                };
+                if let Err(err) = result {
+                    warn!("Could not disconnect from {addr}: {err}");
+                }
+            }
+            Err(Error::Interrupted)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        await_response, backoff_delay, change_setting, cli, default_min_mtu, error, is_link_error,
+        merge_sections, read_response, sample_blocks, write_command, Dedup, Error, MeterCommands,
+        MeterSampleValue, MeterSectionInfo, MeterValue, Progress, Transport,
+    };
+    use bluer::Address;
+    use clap::Parser;
+    use meterreader_models::{
+        Celsius, Command, Humidity, PairMode, ParseError, SampleFlags, TempUnit,
+    };
+    use std::collections::VecDeque;
+    use std::time::{Duration, Instant};
+
+    /// Replays canned responses and records the commands sent.
+    struct MockTransport {
+        responses: VecDeque<Vec<u8>>,
+        sent: Vec<Vec<u8>>,
+        retries: u32,
+        /// The index of a sent command to fail with a timeout, if any.
+        fail_at: Option<usize>,
+        connect_cache: bool,
+        /// Whether the characteristics are looked up, like after connecting.
+        resolved: bool,
+        /// How often the characteristics were looked up.
+        lookups: usize,
+    }
+
+    impl MockTransport {
+        fn new(responses: impl IntoIterator<Item = Vec<u8>>, retries: u32) -> MockTransport {
+            MockTransport {
+                responses: responses.into_iter().collect(),
+                sent: Vec::new(),
+                retries,
+                fail_at: None,
+                connect_cache: true,
+                resolved: false,
+                lookups: 0,
+            }
+        }
+    }
+
+    impl Transport for MockTransport {
+        async fn exec(&mut self, cmd: &Command) -> error::Result<Vec<u8>> {
+            self.before_command();
+            if !self.resolved {
+                self.resolved = true;
+                self.lookups += 1;
+            }
+            self.sent.push(cmd.as_bytes().to_vec());
+            if self.fail_at == Some(self.sent.len() - 1) {
+                return Err(Error::NoResponse(self.address()));
+            }
+            Ok(self.responses.pop_front().expect("unexpected command"))
+        }
+
+        fn command_retries(&self) -> u32 {
+            self.retries
+        }
+
+        fn address(&self) -> Address {
+            Address::any()
+        }
+
+        fn connect_cache(&self) -> bool {
+            self.connect_cache
+        }
+
+        fn reset(&mut self) {
+            self.resolved = false;
+        }
+    }
+
+    /// Yields the given notifications, one per read, like a meter's notify
+    /// stream.
+    struct MockNotifications(VecDeque<Vec<u8>>);
+
+    impl tokio::io::AsyncRead for MockNotifications {
+        fn poll_read(
+            mut self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            buf: &mut tokio::io::ReadBuf<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            if let Some(notification) = self.0.pop_front() {
+                buf.put_slice(&notification);
+            }
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    /// Accepts at most the given number of bytes per write, like a link with a
+    /// small MTU.
+    struct MockWriter(usize);
+
+    impl tokio::io::AsyncWrite for MockWriter {
+        fn poll_write(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            buf: &[u8],
+        ) -> std::task::Poll<std::io::Result<usize>> {
+            std::task::Poll::Ready(Ok(buf.len().min(self.0)))
+        }
+
+        fn poll_flush(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    /// Builds a successful read samples response holding `pairs` sample pairs.
+    fn samples_response(pairs: usize) -> Vec<u8> {
+        let mut response = vec![1];
+        for _ in 0..pairs {
+            response.extend_from_slice(&[152, 40, 119, 152, 40]);
+        }
+        response
+    }
+
+    const SECTION_INFO: MeterSectionInfo = MeterSectionInfo {
+        start_time: 1_637_924_839,
+        end_time: 1_637_925_799,
+        data_length: 8,
+        interval: 120,
+    };
+
+    #[test]
+    fn skips_repeated_readings() {
+        let addr = Address::new([0xd3, 0x4e, 0x07, 0x01, 0x02, 0x03]);
+        let value = |humidity| MeterValue {
+            temperature: Celsius(24.9),
+            humidity: Humidity(humidity),
+            battery: 100,
+            display_unit: TempUnit::Celsius,
+        };
+        let start = Instant::now();
+
+        let mut dedup = Dedup::new(None);
+        assert!(dedup.should_print(addr, &value(40), start));
+        assert!(!dedup.should_print(addr, &value(40), start + Duration::from_secs(5000)));
+        assert!(dedup.should_print(addr, &value(41), start + Duration::from_secs(5001)));
+
+        let mut dedup = Dedup::new(Some(Duration::from_secs(45)));
+        assert!(dedup.should_print(addr, &value(40), start));
+        assert!(!dedup.should_print(addr, &value(40), start + Duration::from_secs(44)));
+        assert!(dedup.should_print(addr, &value(40), start + Duration::from_secs(45)));
+    }
+
+    #[test]
+    fn backs_off_exponentially() {
+        for (attempt, base) in [(1, 250), (2, 500), (3, 1000), (20, 16000)] {
+            let delay = backoff_delay(attempt);
+            assert!(delay >= Duration::from_millis(base));
+            assert!(delay < Duration::from_millis(base * 3 / 2));
+        }
+    }
+
+    #[test]
+    fn reads_trailing_partial_block() {
+        let section_info = MeterSectionInfo {
+            start_time: 1_637_924_839,
+            end_time: 1_637_934_839,
+            data_length: 1030,
+            interval: 120,
+        };
+
+        let blocks = sample_blocks(&section_info, &(0..1030)).collect::<Vec<_>>();
+        assert_eq!(blocks.len(), 172);
+        assert_eq!(blocks[0], (0, 6));
+        assert_eq!(blocks[171], (1026, 4));
+        let count: usize = blocks.iter().map(|(_, count)| usize::from(*count)).sum();
+        assert_eq!(count, 1030);
+
         assert_eq!(
-            result,
-            Some(
-                vec![(24.7, 40), (24.7, 40), (24.7, 40), (24.8, 40)]
-                    .into_iter()
-                    .map(|(temperature, humidity)| MeterSampleValue {
-                        temperature,
-                        humidity
-                    })
-                    .collect()
+            sample_blocks(&section_info, &(1020..1029)).collect::<Vec<_>>(),
+            vec![(1020, 6), (1026, 4)]
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_sections_until_repeated_or_rejected() {
+        let first = vec![1, 97, 160, 191, 231, 97, 162, 162, 63, 4, 6, 0, 120];
+        let second = vec![1, 97, 150, 0, 0, 97, 160, 191, 111, 0, 10, 0, 120];
+        let mut transport = MockTransport::new([first.clone(), second, first.clone()], 0);
+        let sections = transport.read_all_sections().await.unwrap().unwrap();
+        assert_eq!(sections.len(), 2);
+        assert_eq!(sections[1].start_time, 1_637_220_352);
+        assert_eq!(transport.sent[2], [0x57, 0x0f, 59, 2]);
+
+        let mut transport = MockTransport::new([first, vec![3]], 0);
+        let sections = transport.read_all_sections().await.unwrap().unwrap();
+        assert_eq!(sections.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn looks_up_characteristics_again_unless_cached() {
+        let responses = || std::iter::repeat_with(|| vec![1, 25, 2, 0]).take(3);
+
+        let mut transport = MockTransport::new(responses(), 0);
+        transport.read_device_info().await.unwrap();
+        transport.read_device_info().await.unwrap();
+        assert_eq!(transport.lookups, 1);
+        transport.reset();
+        transport.read_device_info().await.unwrap();
+        assert_eq!(transport.lookups, 2);
+
+        let mut transport = MockTransport::new(responses(), 0);
+        transport.connect_cache = false;
+        for _ in 0..3 {
+            transport.read_device_info().await.unwrap();
+        }
+        assert_eq!(transport.lookups, 3);
+    }
+
+    #[tokio::test]
+    async fn fails_on_unparseable_responses() {
+        let mut transport = MockTransport::new([vec![1, 97, 160]], 0);
+        let err = transport.read_section_info(0).await.unwrap_err();
+        assert!(matches!(
+            err,
+            Error::BadResponse(_, ParseError::TooShort { .. })
+        ));
+        assert_eq!(err.exit_code(), 6);
+
+        let mut transport = MockTransport::new([vec![1, 25]], 0);
+        assert!(transport.read_device_info().await.is_err());
+    }
+
+    #[test]
+    fn merges_overlapping_sections() {
+        let sample = |temperature| {
+            Some(MeterSampleValue {
+                temperature: Celsius(temperature),
+                humidity: Humidity(40),
+                flags: SampleFlags::default(),
+            })
+        };
+        let mut samples = vec![
+            (360, sample(21.0)),
+            (480, None),
+            (120, sample(20.0)),
+            (240, sample(20.5)),
+            (480, sample(21.5)),
+            (360, sample(21.0)),
+        ];
+        merge_sections(&mut samples);
+        assert_eq!(
+            samples,
+            vec![
+                (120, sample(20.0)),
+                (240, sample(20.5)),
+                (360, sample(21.0)),
+                (480, sample(21.5)),
+            ]
+        );
+    }
+
+    #[test]
+    fn selects_blocks_for_last_durations() {
+        let section_info = MeterSectionInfo {
+            start_time: 1_637_924_839,
+            end_time: 1_638_048_679,
+            data_length: 1032,
+            interval: 120,
+        };
+        let blocks = |range| sample_blocks(&section_info, &range).collect::<Vec<_>>();
+
+        // Without a duration, every block is read.
+        let all = blocks(section_info.sample_range(None, None));
+        assert_eq!(all.len(), 172);
+        assert_eq!(all[171], (1026, 6));
+
+        // Exactly one block's worth of samples doesn't spill into another.
+        let last = section_info.last_samples(6 * 120);
+        assert_eq!(last, 1026..1032);
+        assert_eq!(blocks(last), vec![(1026, 6)]);
+        assert_eq!(
+            blocks(section_info.last_samples(6 * 120 - 1)),
+            vec![(1026, 6)]
+        );
+        assert_eq!(
+            blocks(section_info.last_samples(7 * 120)),
+            vec![(1020, 6), (1026, 6)]
+        );
+
+        // A duration longer than the history reads all of it.
+        assert_eq!(blocks(section_info.last_samples(365 * 24 * 3600)), all);
+    }
+
+    #[tokio::test]
+    async fn reads_samples_in_range() {
+        let mut transport = MockTransport::new([samples_response(3), samples_response(1)], 0);
+        let (samples, skipped) = transport
+            .read_samples(
+                0,
+                &SECTION_INFO,
+                3..7,
+                PairMode::Both,
+                false,
+                &Progress::hidden(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(skipped, 0);
+
+        assert_eq!(
+            transport.sent,
+            vec![
+                Command::read_samples(0, 6).as_bytes().to_vec(),
+                Command::read_samples(6, 2).as_bytes().to_vec(),
+            ]
+        );
+        let timestamps = samples.iter().map(|(timestamp, _)| *timestamp);
+        assert!(timestamps.eq((3..7).map(|index| SECTION_INFO.timestamp(index))));
+        assert!(samples
+            .iter()
+            .all(|(_, sample)| sample.unwrap().humidity == Humidity(40)));
+    }
+
+    #[tokio::test]
+    async fn reads_one_value_per_pair_of_samples() {
+        let mut transport = MockTransport::new([samples_response(3), samples_response(1)], 0);
+        let (samples, skipped) = transport
+            .read_samples(
+                0,
+                &SECTION_INFO,
+                0..8,
+                PairMode::Average,
+                false,
+                &Progress::hidden(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(skipped, 0);
+        let timestamps = samples.iter().map(|(timestamp, _)| *timestamp);
+        assert!(timestamps.eq([0, 2, 4, 6].map(|index| SECTION_INFO.timestamp(index))));
+        assert!(samples
+            .iter()
+            .all(|(_, sample)| sample.unwrap().temperature == Celsius(24.7)));
+    }
+
+    #[tokio::test]
+    async fn retries_malformed_samples() {
+        let mut transport = MockTransport::new([vec![1, 152], samples_response(1)], 1);
+        let (samples, skipped) = transport
+            .read_samples(
+                0,
+                &SECTION_INFO,
+                6..8,
+                PairMode::Both,
+                false,
+                &Progress::hidden(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(transport.sent.len(), 2);
+        assert_eq!(samples.len(), 2);
+        assert_eq!(skipped, 0);
+
+        // Without retries the block is skipped.
+        let mut transport = MockTransport::new([vec![1, 152]], 0);
+        let (samples, skipped) = transport
+            .read_samples(
+                0,
+                &SECTION_INFO,
+                6..8,
+                PairMode::Both,
+                false,
+                &Progress::hidden(),
             )
+            .await
+            .unwrap();
+        assert_eq!(transport.sent.len(), 1);
+        assert!(samples.is_empty());
+        assert_eq!(skipped, 1);
+    }
+
+    #[tokio::test]
+    async fn keeps_samples_of_other_blocks_when_one_fails() {
+        let mut transport = MockTransport::new([samples_response(3), samples_response(1)], 0);
+        transport.fail_at = Some(0);
+        let (samples, skipped) = transport
+            .read_samples(
+                0,
+                &SECTION_INFO,
+                0..8,
+                PairMode::Both,
+                false,
+                &Progress::hidden(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(transport.sent.len(), 2);
+        assert_eq!(skipped, 1);
+        let timestamps = samples.iter().map(|(timestamp, _)| *timestamp);
+        assert!(timestamps.eq((6..8).map(|index| SECTION_INFO.timestamp(index))));
+
+        // With strict, the first failure ends the dump.
+        let mut transport = MockTransport::new([samples_response(3), samples_response(1)], 0);
+        transport.fail_at = Some(0);
+        assert!(transport
+            .read_samples(
+                0,
+                &SECTION_INFO,
+                0..8,
+                PairMode::Both,
+                true,
+                &Progress::hidden()
+            )
+            .await
+            .is_err());
+        assert_eq!(transport.sent.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn sends_nothing_on_dry_runs() {
+        let cmd = Command::set_display_unit(TempUnit::Fahrenheit);
+        let mut meter = MockTransport::new([], 0);
+        let args = cli::Args::parse_from(["meterreader", "--dry-run", "set-unit", "f"]);
+        change_setting(&mut meter, Address::any(), &args, &cmd, "set display unit")
+            .await
+            .unwrap();
+        assert!(meter.sent.is_empty());
+
+        let mut meter = MockTransport::new([vec![1]], 0);
+        let args = cli::Args::parse_from(["meterreader", "set-unit", "f"]);
+        change_setting(&mut meter, Address::any(), &args, &cmd, "set display unit")
+            .await
+            .unwrap();
+        assert_eq!(meter.sent, [cmd.as_bytes()]);
+    }
+
+    #[test]
+    fn maps_errors_to_exit_codes() {
+        let addr = Address::any();
+        assert_eq!(Error::NoAdapter(String::new()).exit_code(), 3);
+        assert_eq!(Error::MetersNotFound(vec![addr]).exit_code(), 4);
+        assert_eq!(Error::AuthFailed(addr).exit_code(), 5);
+        assert_eq!(Error::InconsistentSectionInfo(addr).exit_code(), 6);
+        assert_eq!(Error::Interrupted.exit_code(), 130);
+        assert_eq!(Error::NoResponse(addr).exit_code(), 7);
+        assert_eq!(
+            Error::MetersFailed {
+                count: 2,
+                exit_code: 5
+            }
+            .exit_code(),
+            5
+        );
+        assert_eq!(
+            Error::Io(std::io::ErrorKind::NotFound.into()).exit_code(),
+            1
+        );
+    }
+
+    #[test]
+    fn recognizes_link_errors() {
+        let addr = Address::any();
+        assert!(is_link_error(&Error::EmptyResponse(addr)));
+        assert!(is_link_error(&Error::Bluetooth(
+            std::io::Error::from(std::io::ErrorKind::BrokenPipe).into()
+        )));
+        assert!(is_link_error(&Error::Bluetooth(bluer::Error {
+            kind: bluer::ErrorKind::NotFound,
+            message: String::new(),
+        })));
+        assert!(!is_link_error(&Error::AuthFailed(addr)));
+        assert!(!is_link_error(&Error::Bluetooth(bluer::Error {
+            kind: bluer::ErrorKind::NotAuthorized,
+            message: String::new(),
+        })));
+    }
+
+    #[tokio::test]
+    async fn rereads_empty_notifications_once() {
+        let addr = Address::any();
+        let cmd = Command::read_samples(0, 2);
+        let timeout = Duration::from_secs(1);
+        let mut buf = [0; 20];
+
+        let mut notifications = MockNotifications([vec![], samples_response(1)].into());
+        let response = read_response(addr, &cmd, &mut notifications, &mut buf, timeout)
+            .await
+            .unwrap();
+        assert_eq!(response, samples_response(1));
+
+        let mut notifications = MockNotifications([vec![], vec![]].into());
+        let err = read_response(addr, &cmd, &mut notifications, &mut buf, timeout)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::EmptyResponse(_)));
+    }
+
+    #[tokio::test]
+    async fn drops_stale_notifications() {
+        let addr = Address::any();
+        let cmd = Command::read_samples(0, 2);
+        let timeout = Duration::from_secs(1);
+        let mut buf = [0; 20];
+        let index_info = vec![1, 97, 160, 191, 231, 97, 162, 162, 63, 4, 6, 0, 120];
+
+        // A stale frame longer than the response, and one shorter.
+        for stale in [index_info, vec![1, 25, 2]] {
+            let mut notifications = MockNotifications([stale, samples_response(1)].into());
+            let response = read_response(addr, &cmd, &mut notifications, &mut buf, timeout)
+                .await
+                .unwrap();
+            assert_eq!(response, samples_response(1));
+        }
+
+        // A stale status byte before a response longer than expected.
+        let cmd = Command::read_alarm_thresholds();
+        let thresholds = vec![1, 0, 30, 10, 60, 20, 1, 0, 0];
+        let mut notifications = MockNotifications([vec![1], thresholds.clone()].into());
+        let response = read_response(addr, &cmd, &mut notifications, &mut buf, timeout)
+            .await
+            .unwrap();
+        assert_eq!(response, thresholds);
+
+        // A longer response split across notifications is kept whole.
+        let mut notifications =
+            MockNotifications([thresholds[..5].to_vec(), thresholds[5..].to_vec()].into());
+        let response = read_response(addr, &cmd, &mut notifications, &mut buf, timeout)
+            .await
+            .unwrap();
+        assert_eq!(response, thresholds);
+    }
+
+    #[test]
+    fn needs_default_mtu_for_a_block_of_samples() {
+        // The 23 bytes every link starts with are enough.
+        assert_eq!(default_min_mtu(), 19);
+    }
+
+    #[tokio::test]
+    async fn rejects_partial_writes() {
+        let cmd = Command::set_time(1_637_924_839);
+        assert!(write_command(&mut MockWriter(20), &cmd).await.is_ok());
+        let err = write_command(&mut MockWriter(10), &cmd).await.unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Io(err) if err.kind() == std::io::ErrorKind::WriteZero
+        ));
+    }
+
+    #[tokio::test]
+    async fn times_out_without_response() {
+        let response = std::future::pending::<std::io::Result<usize>>();
+        let err = await_response(Address::any(), Duration::from_millis(10), response)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::NoResponse(_)));
+        assert_eq!(err.exit_code(), 7);
+    }
+
+    #[test]
+    fn parses_service_data() {
+        let service_data = vec![105, 0, 228, 9, 152, 40];
+        let result = MeterValue::from_data(&service_data);
+        assert_eq!(
+            result,
+            Ok(MeterValue {
+                temperature: Celsius(24.9),
+                humidity: Humidity(40),
+                battery: 100,
+                display_unit: TempUnit::Celsius,
+            })
+        );
+    }
+
+    #[test]
+    fn parses_sample_info() {
+        let response = vec![1, 152, 40, 119, 152, 40, 152, 40, 120, 152, 40];
+        let result = MeterSampleValue::from_response(&response);
+        assert_eq!(
+            result,
+            Ok(vec![(24.7, 40), (24.7, 40), (24.7, 40), (24.8, 40)]
+                .into_iter()
+                .map(|(temperature, humidity)| Some(MeterSampleValue {
+                    temperature: Celsius(temperature),
+                    humidity: Humidity(humidity),
+                    flags: SampleFlags::default(),
+                }))
+                .collect())
         );
     }
 
@@ -353,7 +2700,7 @@ mod tests {
         let result = MeterSectionInfo::from_response(&response);
         assert_eq!(
             result,
-            Some(MeterSectionInfo {
+            Ok(MeterSectionInfo {
                 start_time: 1_637_924_839,
                 end_time: 1_638_048_319,
                 interval: 120,
@@ -0,0 +1,230 @@
+use bluer::Address;
+use rumqttc::{AsyncClient, Event, MqttOptions, Outgoing, QoS};
+use serde_json::json;
+use std::collections::BTreeSet;
+use std::io;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+use meterreader_models::MeterValue;
+
+use crate::output::json_number;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// How long to wait for pending messages to be sent when disconnecting.
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The sensors announced to Home Assistant for every meter, as field name,
+/// unit and device class.
+const SENSORS: [(&str, &str, &str); 3] = [
+    ("temperature", "°C", "temperature"),
+    ("humidity", "%", "humidity"),
+    ("battery", "%", "battery"),
+];
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Broker {
+    host: String,
+    port: u16,
+}
+
+/// Parses a broker URL like `mqtt://localhost:1883`. The scheme and port are
+/// optional.
+pub fn parse_broker(s: &str) -> Result<Broker, &'static str> {
+    let address = s.strip_prefix("mqtt://").unwrap_or(s).trim_end_matches('/');
+    let (host, port) = match address.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().map_err(|_| "invalid port")?),
+        None => (address, 1883),
+    };
+    if host.is_empty() {
+        return Err("missing host");
+    }
+    Ok(Broker {
+        host: host.to_string(),
+        port,
+    })
+}
+
+/// Publishes live readings to `meter/<address>/state`, announcing each meter
+/// via Home Assistant's MQTT discovery the first time it is seen. Messages
+/// are dropped with a warning while the broker can't keep up, so an
+/// unreachable broker never fails reading the meters.
+pub struct Publisher {
+    client: AsyncClient,
+    connection: JoinHandle<()>,
+    announced: BTreeSet<Address>,
+}
+
+impl Publisher {
+    /// Connects to the broker in the background, reconnecting whenever the
+    /// connection is lost. The given meters are announced right away.
+    pub fn connect(broker: &Broker, addresses: &[Address]) -> Publisher {
+        let mut options = MqttOptions::new("meterreader", broker.host.clone(), broker.port);
+        options.set_keep_alive(Duration::from_secs(30));
+        let (client, mut eventloop) = AsyncClient::new(options, 64);
+        let connection = tokio::spawn(async move {
+            loop {
+                match eventloop.poll().await {
+                    Ok(Event::Outgoing(Outgoing::Disconnect)) => break,
+                    Ok(_) => {}
+                    Err(err) => {
+                        warn!("MQTT connection failed ({err}), reconnecting");
+                        tokio::time::sleep(RECONNECT_DELAY).await;
+                    }
+                }
+            }
+        });
+
+        let mut publisher = Publisher {
+            client,
+            connection,
+            announced: BTreeSet::new(),
+        };
+        for addr in addresses {
+            publisher.announce(*addr);
+        }
+        publisher
+    }
+
+    pub fn publish(&mut self, addr: Address, value: &MeterValue) {
+        self.announce(addr);
+        let payload = json!({
+            "temperature": json_number(value.temperature.0),
+            "humidity": value.humidity.0,
+            "battery": value.battery,
+        });
+        self.try_publish(&state_topic(addr), false, payload.to_string());
+    }
+
+    /// Sends all pending messages and disconnects. If that takes longer than
+    /// [`DISCONNECT_TIMEOUT`], e.g. because the broker is unreachable, the
+    /// remaining messages are dropped.
+    pub async fn disconnect(mut self) -> io::Result<()> {
+        let disconnect = async {
+            self.client.disconnect().await.map_err(io::Error::other)?;
+            (&mut self.connection).await.map_err(io::Error::other)
+        };
+        if let Ok(result) = tokio::time::timeout(DISCONNECT_TIMEOUT, disconnect).await {
+            return result;
+        }
+        warn!("Timed out sending pending MQTT messages, dropping them");
+        self.connection.abort();
+        Ok(())
+    }
+
+    /// Announces a meter unless that has been done already. If the
+    /// announcement can't be queued, it is retried with the next reading.
+    fn announce(&mut self, addr: Address) {
+        if self.announced.contains(&addr) {
+            return;
+        }
+
+        let id = object_id(addr);
+        for (field, unit, device_class) in SENSORS {
+            let config = json!({
+                "name": format!("Meter {addr} {field}"),
+                "unique_id": format!("{id}_{field}"),
+                "state_topic": state_topic(addr),
+                "value_template": format!("{{{{ value_json.{field} }}}}"),
+                "unit_of_measurement": unit,
+                "device_class": device_class,
+                "device": {
+                    "identifiers": [id],
+                    "name": format!("Meter {addr}"),
+                    "manufacturer": "SwitchBot",
+                },
+            });
+            let topic = format!("homeassistant/sensor/{id}/{field}/config");
+            if !self.try_publish(&topic, true, config.to_string()) {
+                return;
+            }
+        }
+        self.announced.insert(addr);
+    }
+
+    /// Queues a message, or drops it with a warning if the queue is full.
+    /// Returns whether it was queued.
+    fn try_publish(&self, topic: &str, retain: bool, payload: String) -> bool {
+        match self
+            .client
+            .try_publish(topic, QoS::AtLeastOnce, retain, payload)
+        {
+            Ok(()) => true,
+            Err(err) => {
+                warn!("Dropping MQTT message to {topic}: {err}");
+                false
+            }
+        }
+    }
+}
+
+fn state_topic(addr: Address) -> String {
+    format!("meter/{addr}/state")
+}
+
+/// Returns an identifier for a meter that is valid in discovery topics.
+fn object_id(addr: Address) -> String {
+    format!("meter_{}", addr.to_string().replace(':', "").to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mqtt::{object_id, parse_broker, Broker, Publisher};
+    use bluer::Address;
+    use meterreader_models::{Celsius, Humidity, MeterValue, TempUnit};
+    use rumqttc::{AsyncClient, MqttOptions};
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn parses_broker_urls() {
+        let broker = |host: &str, port| Broker {
+            host: host.to_string(),
+            port,
+        };
+        assert_eq!(
+            parse_broker("mqtt://localhost:1884"),
+            Ok(broker("localhost", 1884))
+        );
+        assert_eq!(
+            parse_broker("mqtt://broker.lan/"),
+            Ok(broker("broker.lan", 1883))
+        );
+        assert_eq!(parse_broker("10.0.0.2:1883"), Ok(broker("10.0.0.2", 1883)));
+        assert!(parse_broker("mqtt://localhost:mqtt").is_err());
+        assert!(parse_broker("mqtt://").is_err());
+    }
+
+    #[test]
+    fn builds_object_ids() {
+        let addr = Address::new([0xd3, 0x4e, 0x07, 0x01, 0x02, 0x03]);
+        assert_eq!(object_id(addr), "meter_d34e07010203");
+    }
+
+    #[tokio::test]
+    async fn drops_messages_while_the_queue_is_full() {
+        let options = MqttOptions::new("meterreader", "localhost", 1883);
+        let (client, _eventloop) = AsyncClient::new(options, 4);
+        let mut publisher = Publisher {
+            client,
+            connection: tokio::spawn(async {}),
+            announced: BTreeSet::new(),
+        };
+        let addr = Address::new([0xd3, 0x4e, 0x07, 0x01, 0x02, 0x03]);
+        let value = MeterValue {
+            temperature: Celsius(21.5),
+            humidity: Humidity(45),
+            battery: 90,
+            display_unit: TempUnit::Celsius,
+        };
+
+        publisher.publish(addr, &value);
+        assert!(publisher.announced.contains(&addr));
+        publisher.publish(addr, &value);
+        publisher.publish(addr, &value);
+
+        let other = Address::new([0xd3, 0x4e, 0x07, 0x01, 0x02, 0x04]);
+        publisher.publish(other, &value);
+        assert!(!publisher.announced.contains(&other));
+    }
+}
@@ -0,0 +1,1094 @@
+use bluer::Address;
+use chrono::{DateTime, Local, TimeZone, Utc};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+use meterreader_models::stats::Summary;
+use meterreader_models::{
+    absolute_humidity, celsius_to_fahrenheit, dew_point, heat_index, AlarmThresholds, Celsius,
+    DeviceInfo, DeviceName, Humidity, MeterSampleValue, MeterValue, SampleFlags,
+};
+
+use crate::cli::{Action, Args, OutputFormat, TimestampFormat};
+use crate::prometheus::Textfile;
+
+#[allow(clippy::struct_excessive_bools)]
+pub struct Printer {
+    out: Box<dyn Write>,
+    format: OutputFormat,
+    fahrenheit: bool,
+    /// The number of decimals temperatures and summary statistics are
+    /// rounded to.
+    precision: u8,
+    temp_offset: f32,
+    humidity_offset: i8,
+    timestamps: TimestampFormat,
+    measurement: String,
+    prefix: String,
+    dew_point: bool,
+    heat_index: bool,
+    absolute_humidity: bool,
+    sample_flags: bool,
+    battery_only: bool,
+    /// The battery level at or below which live readings are printed, from
+    /// `--min-battery`.
+    min_battery: Option<u8>,
+    tag_address: bool,
+    header: bool,
+    /// The directory each meter's samples are written to a file of their own
+    /// in, from `--output-dir`.
+    output_dir: Option<PathBuf>,
+    delimiter: char,
+    pretty: bool,
+    /// Labels printed instead of the addresses of meters, from `--name-map`.
+    labels: BTreeMap<Address, String>,
+    #[cfg(feature = "sqlite")]
+    database: Option<crate::sqlite::Database>,
+    #[cfg(feature = "parquet")]
+    parquet: Option<crate::parquet_file::ParquetFile>,
+    #[cfg(feature = "mqtt")]
+    mqtt: Option<crate::mqtt::Publisher>,
+    #[cfg(feature = "hass")]
+    hass: Option<crate::hass::Client>,
+    prometheus: Option<Textfile>,
+    /// The gauges served with `--serve`, shared with the server.
+    #[cfg(feature = "serve")]
+    served: Option<std::sync::Arc<std::sync::Mutex<crate::prometheus::Gauges>>>,
+}
+
+impl Printer {
+    pub fn new(args: &Args) -> io::Result<Printer> {
+        let out: Box<dyn Write> = match &args.output_file {
+            Some(path) => Box::new(BufWriter::new(
+                OpenOptions::new()
+                    .create(true)
+                    .write(true)
+                    .append(args.append)
+                    .truncate(!args.append)
+                    .open(path)?,
+            )),
+            None => Box::new(io::stdout()),
+        };
+        if let Some(dir) = &args.output_dir {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        Ok(Printer {
+            out,
+            format: args.output,
+            fahrenheit: args.fahrenheit,
+            precision: args.precision,
+            temp_offset: args.temp_offset,
+            humidity_offset: args.humidity_offset,
+            timestamps: if args.utc {
+                TimestampFormat::Utc
+            } else {
+                args.timestamp_format
+            },
+            measurement: args.measurement.clone(),
+            prefix: args.prefix.clone(),
+            dew_point: args.dew_point,
+            heat_index: args.heat_index,
+            absolute_humidity: args.absolute_humidity,
+            sample_flags: args.sample_flags,
+            battery_only: matches!(
+                args.action,
+                Action::Discover {
+                    battery_only: true,
+                    ..
+                }
+            ),
+            min_battery: match args.action {
+                Action::Discover { min_battery, .. } => min_battery,
+                _ => None,
+            },
+            // Historic CSV rows only carry the address if several devices
+            // might end up in the same output.
+            tag_address: args.address.len() != 1,
+            header: args.header,
+            output_dir: args.output_dir.clone(),
+            delimiter: args.delimiter,
+            pretty: args.pretty,
+            labels: match &args.name_map {
+                Some(path) => parse_name_map(&std::fs::read_to_string(path)?).map_err(|err| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Invalid name map {}: {err}", path.display()),
+                    )
+                })?,
+                None => BTreeMap::new(),
+            },
+            #[cfg(feature = "sqlite")]
+            database: args
+                .sqlite
+                .as_deref()
+                .map(crate::sqlite::Database::open)
+                .transpose()
+                .map_err(io::Error::other)?,
+            #[cfg(feature = "parquet")]
+            parquet: args
+                .parquet
+                .clone()
+                .map(crate::parquet_file::ParquetFile::new),
+            #[cfg(feature = "mqtt")]
+            mqtt: args
+                .mqtt
+                .as_ref()
+                .map(|broker| crate::mqtt::Publisher::connect(broker, &args.address)),
+            #[cfg(feature = "hass")]
+            hass: args
+                .hass_url
+                .as_deref()
+                .zip(args.hass_token.as_deref())
+                .map(|(url, token)| crate::hass::Client::new(url, token))
+                .transpose()?,
+            prometheus: args.prometheus.clone().map(Textfile::new),
+            #[cfg(feature = "serve")]
+            served: None,
+        })
+    }
+
+    /// Returns gauges that are kept up to date with the live readings from
+    /// now on, for serving them over HTTP.
+    #[cfg(feature = "serve")]
+    pub fn serve_gauges(&mut self) -> std::sync::Arc<std::sync::Mutex<crate::prometheus::Gauges>> {
+        self.served.get_or_insert_with(Default::default).clone()
+    }
+
+    /// Prints historic samples, given as pairs of Unix timestamps and values.
+    ///
+    /// Samples from gaps in the meter's log, i.e. without values, are printed
+    /// with empty values, or left out of Influx and Graphite output, which
+    /// can't express those.
+    ///
+    /// With `--output-dir`, they are written to a file of their own in that
+    /// directory instead, named `<address>` with an extension matching the
+    /// format, e.g. `.csv` with a header of its own and without the address
+    /// column.
+    pub fn print_samples(
+        &mut self,
+        addr: Address,
+        samples: &[(i64, Option<MeterSampleValue>)],
+    ) -> io::Result<()> {
+        let samples = &samples
+            .iter()
+            .map(|(timestamp, value)| (*timestamp, value.map(|value| self.calibrate_sample(value))))
+            .collect::<Vec<_>>();
+        #[cfg(feature = "sqlite")]
+        if let Some(database) = &mut self.database {
+            database
+                .insert_samples(addr, &meterreader_models::stats::without_gaps(samples))
+                .map_err(io::Error::other)?;
+        }
+        #[cfg(feature = "parquet")]
+        if let Some(parquet) = &mut self.parquet {
+            parquet.add_samples(addr, &meterreader_models::stats::without_gaps(samples));
+        }
+
+        let Some(dir) = &self.output_dir else {
+            return self.write_samples(addr, samples);
+        };
+        let file = BufWriter::new(std::fs::File::create(
+            dir.join(format!("{addr}.{}", file_extension(self.format))),
+        )?);
+        let out = std::mem::replace(&mut self.out, Box::new(file));
+        let header = self.header;
+        let tag_address = std::mem::replace(&mut self.tag_address, false);
+        let result = self.write_samples(addr, samples);
+        self.out = out;
+        self.header = header;
+        self.tag_address = tag_address;
+        result
+    }
+
+    fn write_samples(
+        &mut self,
+        addr: Address,
+        samples: &[(i64, Option<MeterSampleValue>)],
+    ) -> io::Result<()> {
+        // The header is only printed once, even if several devices are dumped.
+        if self.format == OutputFormat::Csv && self.header {
+            self.header = false;
+            let mut columns = vec!["timestamp", "temperature", "humidity"];
+            if self.dew_point {
+                columns.push("dew_point");
+            }
+            if self.heat_index {
+                columns.push("heat_index");
+            }
+            if self.absolute_humidity {
+                columns.push("absolute_humidity");
+            }
+            columns.extend(self.flag_fields(None).into_iter().map(|(name, _)| name));
+            if self.tag_address {
+                columns.push("address");
+            }
+            writeln!(self.out, "{}", csv_row(&columns, self.delimiter))?;
+        }
+
+        for (timestamp, value) in samples {
+            let flags = self.flag_fields(value.as_ref());
+            let time = Local.timestamp_opt(*timestamp, 0).unwrap();
+            let fields = match value {
+                Some(value) => self
+                    .fields(value.temperature.0, value.humidity.0)
+                    .into_iter()
+                    .map(|(name, value)| (name, Some(value)))
+                    .collect::<Vec<_>>(),
+                None => self
+                    .fields(0.0, 0)
+                    .into_iter()
+                    .map(|(name, _)| (name, None))
+                    .collect(),
+            };
+            match self.format {
+                OutputFormat::Csv => {
+                    let mut columns = vec![self.format_time(time)];
+                    columns.extend(fields.iter().map(|(_, value)| {
+                        value.map(|value| value.to_string()).unwrap_or_default()
+                    }));
+                    columns.extend(
+                        flags
+                            .iter()
+                            .map(|(_, flag)| flag.map(|flag| flag.to_string()).unwrap_or_default()),
+                    );
+                    if self.tag_address {
+                        columns.push(self.label(addr));
+                    }
+                    writeln!(self.out, "{}", csv_row(&columns, self.delimiter))?;
+                }
+                OutputFormat::Json => {
+                    let mut object = serde_json::Map::new();
+                    object.insert("address".to_string(), json!(self.label(addr)));
+                    object.insert("timestamp".to_string(), self.json_time(time));
+                    for (name, value) in fields {
+                        object.insert(
+                            name.to_string(),
+                            value.map_or(serde_json::Value::Null, json_number),
+                        );
+                    }
+                    for (name, flag) in flags {
+                        object.insert(name.to_string(), json!(flag));
+                    }
+                    self.write_json(&serde_json::Value::Object(object))?;
+                }
+                OutputFormat::Influx => {
+                    let fields = fields
+                        .into_iter()
+                        .map(|(name, value)| Some((name, value?)))
+                        .collect::<Option<Vec<_>>>();
+                    if let Some(fields) = fields {
+                        writeln!(
+                            self.out,
+                            "{},address={} {} {}",
+                            self.measurement,
+                            influx_tag(&self.label(addr)),
+                            influx_fields(&fields),
+                            time.timestamp_nanos_opt().unwrap_or_default()
+                        )?;
+                    }
+                }
+                OutputFormat::Graphite => {
+                    let fields = fields
+                        .into_iter()
+                        .filter_map(|(name, value)| Some((name, value?)))
+                        .collect::<Vec<_>>();
+                    self.write_graphite(addr, &fields, *timestamp)?;
+                }
+            }
+        }
+
+        self.out.flush()
+    }
+
+    /// Prints a live reading, along with the meter's name and the signal
+    /// strength of the advertisement it came from in dBm if known.
+    ///
+    /// With `--battery-only`, only the battery level is printed.
+    pub fn print_value(
+        &mut self,
+        addr: Address,
+        name: Option<&str>,
+        value: &MeterValue,
+        rssi: Option<i16>,
+    ) -> io::Result<()> {
+        let value = &self.calibrate_value(value);
+        let mut fields = if self.battery_only {
+            Vec::new()
+        } else {
+            self.fields(value.temperature.0, value.humidity.0)
+        };
+        fields.push(("battery", f32::from(value.battery)));
+        let rssi = rssi.filter(|_| !self.battery_only);
+        let now = Local::now();
+        #[cfg(feature = "sqlite")]
+        if let Some(database) = &mut self.database {
+            database
+                .insert_value(addr, now.timestamp(), value)
+                .map_err(io::Error::other)?;
+        }
+        #[cfg(feature = "mqtt")]
+        if let Some(mqtt) = &mut self.mqtt {
+            mqtt.publish(addr, value);
+        }
+        #[cfg(feature = "hass")]
+        if let Some(hass) = &self.hass {
+            hass.update(addr, self.labels.get(&addr).map(String::as_str), value);
+        }
+        if let Some(prometheus) = &mut self.prometheus {
+            prometheus.update(addr, value, rssi);
+        }
+        #[cfg(feature = "serve")]
+        if let Some(served) = &self.served {
+            served.lock().unwrap().update(addr, value, rssi);
+        }
+        // Only the printed readings are filtered, not the ones stored.
+        if self.min_battery.is_some_and(|min| value.battery > min) {
+            return Ok(());
+        }
+
+        match self.format {
+            OutputFormat::Csv => {
+                let unit = if self.fahrenheit { "°F" } else { "°C" };
+                let time = if self.timestamps == TimestampFormat::Local {
+                    String::new()
+                } else {
+                    format!("{} ", self.format_time(now))
+                };
+                let mut descriptions = fields
+                    .iter()
+                    .map(|(name, value)| match *name {
+                        "temperature" => format!("{value}{unit}"),
+                        "dew_point" => format!("{value}{unit} dew point"),
+                        "heat_index" => format!("{value}{unit} heat index"),
+                        "absolute_humidity" => format!("{value} g/m³ absolute humidity"),
+                        _ => format!("{value}% {name}"),
+                    })
+                    .collect::<Vec<_>>();
+                if let Some(rssi) = rssi {
+                    descriptions.push(format!("{rssi} dBm"));
+                }
+                let addr = self.label(addr);
+                let label = name.map_or_else(|| addr.clone(), |name| format!("{name} ({addr})"));
+                writeln!(self.out, "{time}{label}: {}", descriptions.join(", "))?;
+            }
+            OutputFormat::Json => {
+                let mut object = serde_json::Map::new();
+                object.insert("address".to_string(), json!(self.label(addr)));
+                if let Some(name) = name {
+                    object.insert("name".to_string(), json!(name));
+                }
+                object.insert("timestamp".to_string(), self.json_time(now));
+                for (name, value) in fields {
+                    object.insert(name.to_string(), json_number(value));
+                }
+                if let Some(rssi) = rssi {
+                    object.insert("rssi".to_string(), json!(rssi));
+                }
+                self.write_json(&serde_json::Value::Object(object))?;
+            }
+            OutputFormat::Influx => writeln!(
+                self.out,
+                "{},address={} {} {}",
+                self.measurement,
+                influx_tag(&self.label(addr)),
+                influx_fields(&fields),
+                now.timestamp_nanos_opt().unwrap_or_default()
+            )?,
+            OutputFormat::Graphite => {
+                if let Some(rssi) = rssi {
+                    fields.push(("rssi", f32::from(rssi)));
+                }
+                self.write_graphite(addr, &fields, now.timestamp())?;
+            }
+        }
+
+        self.out.flush()
+    }
+
+    /// Prints summary statistics of historic samples instead of the samples
+    /// themselves.
+    pub fn print_summary(
+        &mut self,
+        addr: Address,
+        samples: &[(i64, MeterSampleValue)],
+    ) -> io::Result<()> {
+        let (Some((first, _)), Some((last, _))) = (samples.first(), samples.last()) else {
+            return Ok(());
+        };
+        let from = Local.timestamp_opt(*first, 0).unwrap();
+        let to = Local.timestamp_opt(*last, 0).unwrap();
+        let samples = samples
+            .iter()
+            .map(|(timestamp, value)| (*timestamp, self.calibrate_sample(*value)))
+            .collect::<Vec<_>>();
+        let (temperatures, humidities) = self.sample_columns(&samples);
+        let temperature = Summary::of(temperatures);
+        let humidity = Summary::of(humidities);
+        let summaries = [("temperature", temperature), ("humidity", humidity)]
+            .into_iter()
+            .filter_map(|(name, summary)| Some((name, self.round_summary(summary?))))
+            .collect::<Vec<_>>();
+
+        match self.format {
+            OutputFormat::Csv => {
+                let descriptions = summaries
+                    .iter()
+                    .map(|(name, summary)| self.describe_summary(name, summary))
+                    .collect::<Vec<_>>();
+                writeln!(
+                    self.out,
+                    "{}: {} samples from {} to {}: {}",
+                    self.label(addr),
+                    samples.len(),
+                    self.format_time(from),
+                    self.format_time(to),
+                    descriptions.join("; ")
+                )?;
+            }
+            OutputFormat::Json => {
+                let mut object = serde_json::Map::new();
+                object.insert("address".to_string(), json!(self.label(addr)));
+                object.insert("from".to_string(), self.json_time(from));
+                object.insert("to".to_string(), self.json_time(to));
+                object.insert("count".to_string(), json!(samples.len()));
+                for (name, summary) in &summaries {
+                    object.insert(
+                        (*name).to_string(),
+                        json!({
+                            "min": json_number(summary.min),
+                            "max": json_number(summary.max),
+                            "mean": json_number(summary.mean),
+                            "std_dev": json_number(summary.std_dev),
+                        }),
+                    );
+                }
+                self.write_json(&serde_json::Value::Object(object))?;
+            }
+            OutputFormat::Influx => {
+                let mut fields = vec![format!("count={}i", samples.len())];
+                for (name, summary) in &summaries {
+                    fields.push(influx_fields(&[
+                        (&format!("{name}_min"), summary.min),
+                        (&format!("{name}_max"), summary.max),
+                        (&format!("{name}_mean"), summary.mean),
+                        (&format!("{name}_std_dev"), summary.std_dev),
+                    ]));
+                }
+                writeln!(
+                    self.out,
+                    "{}_summary,address={} {} {}",
+                    self.measurement,
+                    influx_tag(&self.label(addr)),
+                    fields.join(","),
+                    to.timestamp_nanos_opt().unwrap_or_default()
+                )?;
+            }
+            OutputFormat::Graphite => {
+                #[allow(clippy::cast_precision_loss)]
+                let mut fields = vec![("count".to_string(), samples.len() as f32)];
+                for (name, summary) in &summaries {
+                    fields.extend([
+                        (format!("{name}_min"), summary.min),
+                        (format!("{name}_max"), summary.max),
+                        (format!("{name}_mean"), summary.mean),
+                        (format!("{name}_std_dev"), summary.std_dev),
+                    ]);
+                }
+                let fields = fields
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), *value))
+                    .collect::<Vec<_>>();
+                self.write_graphite(addr, &fields, to.timestamp())?;
+            }
+        }
+
+        self.out.flush()
+    }
+
+    /// Prints a meter's device info, along with its name if it could be
+    /// read.
+    pub fn print_device_info(
+        &mut self,
+        addr: Address,
+        device_info: &DeviceInfo,
+        name: Option<&DeviceName>,
+    ) -> io::Result<()> {
+        if self.format == OutputFormat::Json {
+            let mut object = json!({
+                "address": self.label(addr),
+                "firmware": device_info.firmware,
+                "hardware": device_info.hardware,
+            });
+            if let Some(name) = name {
+                object["name"] = json!(name.0);
+            }
+            self.write_json(&object)?;
+        } else {
+            let name = name
+                .map(|name| format!(", name \"{name}\""))
+                .unwrap_or_default();
+            writeln!(
+                self.out,
+                "{}: firmware {}, hardware {}{name}",
+                self.label(addr),
+                device_info.firmware,
+                device_info.hardware
+            )?;
+        }
+
+        self.out.flush()
+    }
+
+    /// Prints the thresholds of a meter's alarms.
+    pub fn print_alarm_thresholds(
+        &mut self,
+        addr: Address,
+        thresholds: &AlarmThresholds,
+    ) -> io::Result<()> {
+        let temp_high = self.output_temperature(thresholds.temp_high.0);
+        let temp_low = self.output_temperature(thresholds.temp_low.0);
+        if self.format == OutputFormat::Json {
+            self.write_json(&json!({
+                "address": self.label(addr),
+                "temp_high": json_number(temp_high),
+                "temp_low": json_number(temp_low),
+                "hum_high": thresholds.hum_high.0,
+                "hum_low": thresholds.hum_low.0,
+            }))?;
+        } else {
+            let unit = if self.fahrenheit { "°F" } else { "°C" };
+            writeln!(
+                self.out,
+                "{}: temperature alarm below {temp_low}{unit} or above {temp_high}{unit}, humidity alarm below {}% or above {}%",
+                self.label(addr),
+                thresholds.hum_low.0,
+                thresholds.hum_high.0
+            )?;
+        }
+
+        self.out.flush()
+    }
+
+    /// Writes out everything collected during a scan.
+    pub fn end_scan(&mut self) -> io::Result<()> {
+        if let Some(prometheus) = &self.prometheus {
+            prometheus.write()?;
+        }
+        self.out.flush()
+    }
+
+    /// Flushes the output and closes connections to external services.
+    #[cfg_attr(
+        not(any(feature = "mqtt", feature = "hass")),
+        allow(clippy::unused_async)
+    )]
+    pub async fn finish(&mut self) -> io::Result<()> {
+        self.end_scan()?;
+        #[cfg(feature = "parquet")]
+        if let Some(parquet) = self.parquet.take() {
+            parquet.write().map_err(io::Error::other)?;
+        }
+        #[cfg(feature = "mqtt")]
+        if let Some(mqtt) = self.mqtt.take() {
+            mqtt.disconnect().await?;
+        }
+        #[cfg(feature = "hass")]
+        if let Some(hass) = self.hass.take() {
+            hass.finish().await;
+        }
+        self.out.flush()
+    }
+
+    fn convert_temperature(&self, celsius: f32) -> f32 {
+        if self.fahrenheit {
+            celsius_to_fahrenheit(celsius)
+        } else {
+            celsius
+        }
+    }
+
+    /// Converts a temperature to the output unit and rounds it to
+    /// `--precision` decimals.
+    fn output_temperature(&self, celsius: f32) -> f32 {
+        self.round(self.convert_temperature(celsius))
+    }
+
+    /// Rounds a value to the number of decimals from `--precision`.
+    fn round(&self, value: f32) -> f32 {
+        let factor = 10f32.powi(i32::from(self.precision));
+        (value * factor).round() / factor
+    }
+
+    /// Describes the summary of the named column in words, for CSV output.
+    fn describe_summary(&self, name: &str, summary: &Summary) -> String {
+        let unit = match name {
+            "temperature" if self.fahrenheit => "°F",
+            "temperature" => "°C",
+            _ => "%",
+        };
+        let precision = usize::from(self.precision);
+        format!(
+            "{name} min {:.*}{unit}, max {:.*}{unit}, mean {:.*}{unit}, std dev {:.*}{unit}",
+            precision,
+            summary.min,
+            precision,
+            summary.max,
+            precision,
+            summary.mean,
+            precision,
+            summary.std_dev
+        )
+    }
+
+    /// Rounds the statistics of a summary to `--precision`, so corrected or
+    /// converted temperatures print without artifacts like 23.199999.
+    fn round_summary(&self, summary: Summary) -> Summary {
+        Summary {
+            min: self.round(summary.min),
+            max: self.round(summary.max),
+            mean: self.round(summary.mean),
+            std_dev: self.round(summary.std_dev),
+            ..summary
+        }
+    }
+
+    /// Corrects a reading by the offsets from `--temp-offset` and
+    /// `--humidity-offset`.
+    fn calibrate(&self, temperature: f32, humidity: u8) -> (Celsius, Humidity) {
+        let humidity = i16::from(humidity) + i16::from(self.humidity_offset);
+        (
+            Celsius(temperature + self.temp_offset),
+            Humidity(u8::try_from(humidity.clamp(0, 100)).unwrap_or_default()),
+        )
+    }
+
+    /// Corrects a live reading, before it is printed or handed to any other
+    /// output, so every output gets the same values.
+    fn calibrate_value(&self, value: &MeterValue) -> MeterValue {
+        let (temperature, humidity) = self.calibrate(value.temperature.0, value.humidity.0);
+        MeterValue {
+            temperature,
+            humidity,
+            battery: value.battery,
+            display_unit: value.display_unit,
+        }
+    }
+
+    /// Corrects a historic sample like [`Printer::calibrate_value`].
+    fn calibrate_sample(&self, value: MeterSampleValue) -> MeterSampleValue {
+        let (temperature, humidity) = self.calibrate(value.temperature.0, value.humidity.0);
+        MeterSampleValue {
+            temperature,
+            humidity,
+            flags: value.flags,
+        }
+    }
+
+    /// Returns the converted temperatures and humidities of historic
+    /// samples.
+    fn sample_columns(&self, samples: &[(i64, MeterSampleValue)]) -> (Vec<f32>, Vec<f32>) {
+        samples
+            .iter()
+            .map(|(_, value)| {
+                (
+                    self.convert_temperature(value.temperature.0),
+                    f32::from(value.humidity),
+                )
+            })
+            .unzip()
+    }
+
+    /// Returns the flags of a historic sample printed with `--sample-flags`,
+    /// which are `None` for gaps.
+    fn flag_fields(&self, value: Option<&MeterSampleValue>) -> Vec<(&'static str, Option<bool>)> {
+        if !self.sample_flags {
+            return Vec::new();
+        }
+        let flags = value.map(|value| value.flags);
+        vec![
+            (
+                "suspect_temperature",
+                flags.map(SampleFlags::suspect_temperature),
+            ),
+            ("suspect_humidity", flags.map(SampleFlags::suspect_humidity)),
+        ]
+    }
+
+    /// Returns the named values printed for a reading, in output order.
+    fn fields(&self, temperature: f32, humidity: u8) -> Vec<(&'static str, f32)> {
+        let mut fields = vec![
+            ("temperature", self.output_temperature(temperature)),
+            ("humidity", f32::from(humidity)),
+        ];
+        if self.dew_point {
+            fields.push((
+                "dew_point",
+                self.output_temperature(dew_point(temperature, humidity)),
+            ));
+        }
+        if self.heat_index {
+            fields.push((
+                "heat_index",
+                self.output_temperature(heat_index(temperature, humidity)),
+            ));
+        }
+        if self.absolute_humidity {
+            fields.push((
+                "absolute_humidity",
+                absolute_humidity(temperature, humidity),
+            ));
+        }
+        fields
+    }
+
+    /// Returns the label of a meter from the name map, or its address if it
+    /// has none.
+    fn label(&self, addr: Address) -> String {
+        self.labels
+            .get(&addr)
+            .cloned()
+            .unwrap_or_else(|| addr.to_string())
+    }
+
+    fn format_time(&self, time: DateTime<Local>) -> String {
+        match self.timestamps {
+            TimestampFormat::Local => time.to_string(),
+            TimestampFormat::Utc => time
+                .with_timezone(&Utc)
+                .format("%Y-%m-%d %H:%M:%S %:z")
+                .to_string(),
+            TimestampFormat::Epoch => time.timestamp().to_string(),
+        }
+    }
+
+    fn json_time(&self, time: DateTime<Local>) -> serde_json::Value {
+        match self.timestamps {
+            TimestampFormat::Local => json!(time.to_rfc3339()),
+            TimestampFormat::Utc => json!(time.with_timezone(&Utc).to_rfc3339()),
+            TimestampFormat::Epoch => json!(time.timestamp()),
+        }
+    }
+
+    /// Writes one Graphite plaintext line per field, with metric paths of the
+    /// form `<prefix>.<meter>.<field>`.
+    fn write_graphite(
+        &mut self,
+        addr: Address,
+        fields: &[(&str, f32)],
+        timestamp: i64,
+    ) -> io::Result<()> {
+        let meter = graphite_component(&self.label(addr));
+        for (name, value) in fields {
+            writeln!(
+                self.out,
+                "{}.{meter}.{name} {value} {timestamp}",
+                self.prefix
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes a JSON value on a line of its own, or indented over several
+    /// lines with `--pretty`.
+    fn write_json(&mut self, value: &serde_json::Value) -> io::Result<()> {
+        if self.pretty {
+            serde_json::to_writer_pretty(&mut self.out, value)?;
+        } else {
+            serde_json::to_writer(&mut self.out, value)?;
+        }
+        writeln!(self.out)
+    }
+}
+
+/// Returns the extension of files written in the given format.
+fn file_extension(format: OutputFormat) -> &'static str {
+    match format {
+        OutputFormat::Csv => "csv",
+        OutputFormat::Json => "json",
+        OutputFormat::Influx | OutputFormat::Graphite => "txt",
+    }
+}
+
+/// Joins the fields of a CSV row with `delimiter`, quoting fields that
+/// contain it, e.g. local timestamps when delimiting with spaces.
+fn csv_row(fields: &[impl AsRef<str>], delimiter: char) -> String {
+    fields
+        .iter()
+        .map(|field| {
+            let field = field.as_ref();
+            if field.contains(delimiter) || field.contains('"') {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(&delimiter.to_string())
+}
+
+/// Parses a name map, a TOML file of `"address" = "label"` pairs.
+fn parse_name_map(content: &str) -> Result<BTreeMap<Address, String>, String> {
+    let map: BTreeMap<String, String> = toml::from_str(content).map_err(|err| err.to_string())?;
+    map.into_iter()
+        .map(|(addr, label)| {
+            let addr = addr
+                .parse()
+                .map_err(|_| format!("invalid Bluetooth address {addr}"))?;
+            Ok((addr, label))
+        })
+        .collect()
+}
+
+/// Escapes a tag value for the Influx line protocol.
+fn influx_tag(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
+
+/// Turns a label into a single component of a Graphite metric path, i.e.
+/// replaces the separators of paths, fields and addresses with underscores.
+fn graphite_component(label: &str) -> String {
+    label
+        .chars()
+        .map(|c| {
+            if c == '.' || c == ':' || c.is_whitespace() {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+fn influx_fields(fields: &[(&str, f32)]) -> String {
+    fields
+        .iter()
+        .map(|(name, value)| format!("{name}={value}"))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Converts a float to a JSON number without the artifacts of widening it to
+/// `f64`, i.e. 24.9 stays 24.9 instead of becoming 24.899999618530273.
+pub fn json_number(value: f32) -> serde_json::Value {
+    value.to_string().parse().unwrap_or(serde_json::Value::Null)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::{Args, OutputFormat};
+    use crate::output::{
+        csv_row, file_extension, graphite_component, influx_fields, influx_tag, json_number,
+        parse_name_map, Printer,
+    };
+    use bluer::Address;
+    use clap::Parser;
+    use meterreader_models::{
+        dew_point, Celsius, Humidity, MeterSampleValue, MeterValue, SampleFlags, TempUnit,
+    };
+
+    #[test]
+    fn converts_floats_to_json_without_artifacts() {
+        assert_eq!(json_number(24.9).to_string(), "24.9");
+        assert_eq!(json_number(40.0).to_string(), "40");
+    }
+
+    #[test]
+    fn formats_influx_fields() {
+        assert_eq!(
+            influx_fields(&[("temperature", 24.9), ("humidity", 40.0)]),
+            "temperature=24.9,humidity=40"
+        );
+    }
+
+    #[test]
+    fn escapes_influx_tags() {
+        assert_eq!(influx_tag("D3:4E:07:01:02:03"), "D3:4E:07:01:02:03");
+        assert_eq!(influx_tag("Living room, a=b"), "Living\\ room\\,\\ a\\=b");
+    }
+
+    #[test]
+    fn sanitizes_graphite_components() {
+        assert_eq!(graphite_component("D3:4E:07:01:02:03"), "D3_4E_07_01_02_03");
+        assert_eq!(graphite_component("Living room 1.5"), "Living_room_1_5");
+    }
+
+    #[test]
+    fn names_files_after_the_output_format() {
+        assert_eq!(file_extension(OutputFormat::Csv), "csv");
+        assert_eq!(file_extension(OutputFormat::Json), "json");
+        assert_eq!(file_extension(OutputFormat::Influx), "txt");
+
+        let dir = std::env::temp_dir().join(format!("meterreader-dir-{}", std::process::id()));
+        let args = Args::parse_from([
+            "meterreader".as_ref(),
+            "--output".as_ref(),
+            "json".as_ref(),
+            "--output-dir".as_ref(),
+            dir.as_os_str(),
+            "discover".as_ref(),
+        ]);
+        let mut printer = Printer::new(&args).unwrap();
+        let sample = MeterSampleValue {
+            temperature: Celsius(21.5),
+            humidity: Humidity(45),
+            flags: SampleFlags::default(),
+        };
+        printer
+            .print_samples(Address::any(), &[(1_637_924_839, Some(sample))])
+            .unwrap();
+        assert!(dir.join("00:00:00:00:00:00.json").exists());
+        assert!(!dir.join("00:00:00:00:00:00.csv").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parses_name_maps() {
+        let labels = parse_name_map(
+            "\"D3:4E:07:01:02:03\" = \"Garage\"\n\
+             \"00:00:00:00:00:00\" = \"Nursery\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            labels.get(&Address::new([0xd3, 0x4e, 0x07, 0x01, 0x02, 0x03])),
+            Some(&"Garage".to_string())
+        );
+        assert_eq!(labels.get(&Address::any()), Some(&"Nursery".to_string()));
+
+        assert!(parse_name_map("garage = \"Garage\"").is_err());
+    }
+
+    #[test]
+    fn quotes_csv_fields_containing_the_delimiter() {
+        let row = ["2022-06-01 14:00:00 +02:00", "24.9", "40"];
+        assert_eq!(csv_row(&row, ','), "2022-06-01 14:00:00 +02:00,24.9,40");
+        assert_eq!(csv_row(&row, ' '), "\"2022-06-01 14:00:00 +02:00\" 24.9 40");
+        assert_eq!(csv_row(&["a\"b"], '\t'), "\"a\"\"b\"");
+    }
+
+    #[test]
+    fn applies_calibration_offsets_once() {
+        let dir =
+            std::env::temp_dir().join(format!("meterreader-calibration-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("readings.json");
+        let prometheus = dir.join("meters.prom");
+        let args = Args::parse_from([
+            "meterreader".as_ref(),
+            "--temp-offset".as_ref(),
+            "-1.5".as_ref(),
+            "--humidity-offset".as_ref(),
+            "3".as_ref(),
+            "--dew-point".as_ref(),
+            "--output".as_ref(),
+            "json".as_ref(),
+            "--output-file".as_ref(),
+            output.as_os_str(),
+            "--prometheus".as_ref(),
+            prometheus.as_os_str(),
+            "discover".as_ref(),
+        ]);
+        let mut printer = Printer::new(&args).unwrap();
+        let value = MeterValue {
+            temperature: Celsius(26.5),
+            humidity: Humidity(40),
+            battery: 100,
+            display_unit: TempUnit::Celsius,
+        };
+        printer
+            .print_value(Address::any(), None, &value, None)
+            .unwrap();
+        printer.end_scan().unwrap();
+
+        let reading: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&output).unwrap()).unwrap();
+        assert_eq!(reading["temperature"], 25.0);
+        assert_eq!(reading["humidity"], 43);
+        // Derived values are computed from the corrected reading.
+        assert_eq!(
+            reading["dew_point"],
+            json_number((dew_point(25.0, 43) * 10.0).round() / 10.0)
+        );
+        // Other outputs get the corrected reading as well.
+        let gauges = std::fs::read_to_string(&prometheus).unwrap();
+        assert!(gauges.contains("meter_temperature_celsius{address=\"00:00:00:00:00:00\"} 25\n"));
+        assert!(gauges.contains("meter_humidity_percent{address=\"00:00:00:00:00:00\"} 43\n"));
+        std::fs::remove_dir_all(dir).unwrap();
+
+        let sample = MeterSampleValue {
+            temperature: Celsius(26.5),
+            humidity: Humidity(99),
+            flags: SampleFlags::default(),
+        };
+        assert_eq!(printer.calibrate_sample(sample).humidity, Humidity(100));
+    }
+
+    #[test]
+    fn rounds_temperatures_to_precision() {
+        let args = Args::parse_from(["meterreader", "--temp-offset", "-1.5", "discover"]);
+        let printer = Printer::new(&args).unwrap();
+        let sample = printer.calibrate_sample(MeterSampleValue {
+            temperature: Celsius(24.7),
+            humidity: Humidity(40),
+            flags: SampleFlags::default(),
+        });
+        // Without rounding, this would print as 23.199999.
+        assert_eq!(
+            printer.fields(sample.temperature.0, sample.humidity.0)[0]
+                .1
+                .to_string(),
+            "23.2"
+        );
+
+        let args = Args::parse_from(["meterreader", "--precision", "0", "--dew-point", "discover"]);
+        let fields = Printer::new(&args).unwrap().fields(24.7, 40);
+        assert_eq!(fields[0].1.to_string(), "25");
+        assert_eq!(fields[2].1.to_string(), "10");
+    }
+
+    #[test]
+    fn rounds_summaries_to_precision() {
+        let dir = std::env::temp_dir().join(format!("meterreader-summary-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("summary.txt");
+        let args = Args::parse_from([
+            "meterreader".as_ref(),
+            "--temp-offset".as_ref(),
+            "-1.5".as_ref(),
+            "--output-file".as_ref(),
+            output.as_os_str(),
+            "discover".as_ref(),
+        ]);
+        let mut printer = Printer::new(&args).unwrap();
+        let sample = |temperature| MeterSampleValue {
+            temperature: Celsius(temperature),
+            humidity: Humidity(40),
+            flags: SampleFlags::default(),
+        };
+        printer
+            .print_summary(
+                Address::any(),
+                &[(1_637_924_839, sample(24.7)), (1_637_924_959, sample(24.9))],
+            )
+            .unwrap();
+        drop(printer);
+
+        let summary = std::fs::read_to_string(&output).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(
+            summary.contains("temperature min 23.2°C, max 23.4°C, mean 23.3°C, std dev 0.1°C"),
+            "{summary}"
+        );
+        assert!(
+            summary.contains("humidity min 40.0%, max 40.0%"),
+            "{summary}"
+        );
+    }
+}
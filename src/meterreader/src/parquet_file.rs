@@ -0,0 +1,122 @@
+use arrow_array::{
+    ArrayRef, Float32Array, RecordBatch, StringArray, TimestampSecondArray, UInt8Array,
+};
+use arrow_schema::{ArrowError, DataType, Field, Schema, TimeUnit};
+use bluer::Address;
+use parquet::arrow::ArrowWriter;
+use parquet::errors::ParquetError;
+use std::fs::File;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use meterreader_models::MeterSampleValue;
+
+/// Collects historic samples and writes them to a Parquet file in one go when
+/// done. Like in the `SQLite` database, temperatures are always stored in
+/// degrees Celsius.
+pub struct ParquetFile {
+    path: PathBuf,
+    addresses: Vec<String>,
+    timestamps: Vec<i64>,
+    temperatures: Vec<f32>,
+    humidities: Vec<u8>,
+}
+
+impl ParquetFile {
+    #[must_use]
+    pub fn new(path: PathBuf) -> ParquetFile {
+        ParquetFile {
+            path,
+            addresses: Vec::new(),
+            timestamps: Vec::new(),
+            temperatures: Vec::new(),
+            humidities: Vec::new(),
+        }
+    }
+
+    pub fn add_samples(&mut self, addr: Address, samples: &[(i64, MeterSampleValue)]) {
+        for (timestamp, sample) in samples {
+            self.addresses.push(addr.to_string());
+            self.timestamps.push(*timestamp);
+            self.temperatures.push(sample.temperature.0);
+            self.humidities.push(sample.humidity.0);
+        }
+    }
+
+    fn record_batch(self) -> Result<RecordBatch, ArrowError> {
+        let schema = Schema::new(vec![
+            Field::new("address", DataType::Utf8, false),
+            Field::new(
+                "timestamp",
+                DataType::Timestamp(TimeUnit::Second, Some("UTC".into())),
+                false,
+            ),
+            Field::new("temperature", DataType::Float32, false),
+            Field::new("humidity", DataType::UInt8, false),
+        ]);
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from(self.addresses)),
+            Arc::new(TimestampSecondArray::from(self.timestamps).with_timezone("UTC")),
+            Arc::new(Float32Array::from(self.temperatures)),
+            Arc::new(UInt8Array::from(self.humidities)),
+        ];
+        RecordBatch::try_new(Arc::new(schema), columns)
+    }
+
+    /// Writes all samples collected to the file, replacing it if it exists.
+    pub fn write(self) -> Result<(), ParquetError> {
+        let file = File::create(&self.path)?;
+        let batch = self.record_batch()?;
+        let mut writer = ArrowWriter::try_new(file, batch.schema(), None)?;
+        writer.write(&batch)?;
+        writer.close()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parquet_file::ParquetFile;
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Float32Type, TimestampSecondType, UInt8Type};
+    use bluer::Address;
+    use meterreader_models::{Celsius, Humidity, MeterSampleValue, SampleFlags};
+
+    #[test]
+    fn builds_one_row_per_sample() {
+        let mut file = ParquetFile::new("samples.parquet".into());
+        let sample = MeterSampleValue {
+            temperature: Celsius(24.9),
+            humidity: Humidity(40),
+            flags: SampleFlags::default(),
+        };
+        file.add_samples(
+            Address::new([0xd3, 0x4e, 0x07, 0x01, 0x02, 0x03]),
+            &[(1000, sample)],
+        );
+        file.add_samples(Address::any(), &[(1120, sample), (1240, sample)]);
+
+        let batch = file.record_batch().unwrap();
+        assert_eq!(batch.num_rows(), 3);
+        assert_eq!(
+            batch.column(0).as_string::<i32>().value(0),
+            "D3:4E:07:01:02:03"
+        );
+        assert_eq!(
+            batch
+                .column(1)
+                .as_primitive::<TimestampSecondType>()
+                .values(),
+            &[1000, 1120, 1240]
+        );
+        assert_eq!(
+            batch
+                .column(2)
+                .as_primitive::<Float32Type>()
+                .value(2)
+                .to_string(),
+            "24.9"
+        );
+        assert_eq!(batch.column(3).as_primitive::<UInt8Type>().value(1), 40);
+    }
+}
@@ -0,0 +1,76 @@
+#[cfg(feature = "progress")]
+use std::io::IsTerminal;
+#[cfg(feature = "progress")]
+use std::sync::LazyLock;
+
+/// All progress bars, so the bars of meters read at the same time are drawn
+/// below each other.
+#[cfg(feature = "progress")]
+static BARS: LazyLock<indicatif::MultiProgress> = LazyLock::new(indicatif::MultiProgress::new);
+
+/// A progress bar for reads that take many commands, e.g. dumping a meter's
+/// whole history.
+///
+/// It is drawn on stderr, and only if that is a terminal and the `progress`
+/// feature is enabled, so it never ends up in the output on stdout.
+pub struct Progress {
+    #[cfg(feature = "progress")]
+    bar: Option<indicatif::ProgressBar>,
+}
+
+impl Progress {
+    // Without the feature, there is nothing to show.
+    #![cfg_attr(
+        not(feature = "progress"),
+        allow(unused_variables, clippy::unused_self)
+    )]
+
+    /// Creates a progress bar labeled with `label`, which is never shown if
+    /// `enabled` is false.
+    #[must_use]
+    pub fn new(label: &str, enabled: bool) -> Progress {
+        Progress {
+            #[cfg(feature = "progress")]
+            bar: (enabled && std::io::stderr().is_terminal()).then(|| {
+                let bar = BARS.add(indicatif::ProgressBar::new(0));
+                bar.set_style(
+                    indicatif::ProgressStyle::with_template("{prefix} {bar:40} {pos}/{len} blocks")
+                        .unwrap(),
+                );
+                bar.set_prefix(label.to_string());
+                bar
+            }),
+        }
+    }
+
+    /// Creates a progress bar that is never shown.
+    #[must_use]
+    pub fn hidden() -> Progress {
+        Progress::new("", false)
+    }
+
+    /// Starts showing the progress of `len` steps.
+    pub fn start(&self, len: usize) {
+        #[cfg(feature = "progress")]
+        if let Some(bar) = &self.bar {
+            bar.set_length(len as u64);
+            bar.set_position(0);
+        }
+    }
+
+    /// Marks one more step as done.
+    pub fn inc(&self) {
+        #[cfg(feature = "progress")]
+        if let Some(bar) = &self.bar {
+            bar.inc(1);
+        }
+    }
+
+    /// Removes the progress bar again.
+    pub fn finish(&self) {
+        #[cfg(feature = "progress")]
+        if let Some(bar) = &self.bar {
+            bar.finish_and_clear();
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use bluer::Address;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use meterreader_models::MeterValue;
+
+/// The exported gauges, as name and help text.
+const GAUGES: [(&str, &str); 4] = [
+    (
+        "meter_temperature_celsius",
+        "Temperature in degrees Celsius",
+    ),
+    ("meter_humidity_percent", "Relative humidity in percent"),
+    ("meter_battery_percent", "Battery level in percent"),
+    (
+        "meter_rssi_dbm",
+        "Signal strength of the latest advertisement in dBm",
+    ),
+];
+
+/// The latest reading of every meter, as gauges in the Prometheus text
+/// format.
+#[derive(Default)]
+pub struct Gauges {
+    /// The latest values of every meter, in the order of [`GAUGES`]. A value
+    /// that isn't known, like a missing signal strength, is left out.
+    values: BTreeMap<Address, [Option<f32>; 4]>,
+}
+
+impl Gauges {
+    pub fn update(&mut self, addr: Address, value: &MeterValue, rssi: Option<i16>) {
+        self.values.insert(
+            addr,
+            [
+                Some(value.temperature.0),
+                Some(f32::from(value.humidity.0)),
+                Some(f32::from(value.battery)),
+                rssi.map(f32::from),
+            ],
+        );
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for (i, (name, help)) in GAUGES.iter().enumerate() {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} gauge");
+            for (addr, values) in &self.values {
+                if let Some(value) = values[i] {
+                    let _ = writeln!(out, "{name}{{address=\"{addr}\"}} {value}");
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Writes the latest reading of every meter as gauges in the Prometheus text
+/// format, for use with the textfile collector of the node exporter.
+pub struct Textfile {
+    path: PathBuf,
+    gauges: Gauges,
+}
+
+impl Textfile {
+    pub fn new(path: PathBuf) -> Textfile {
+        Textfile {
+            path,
+            gauges: Gauges::default(),
+        }
+    }
+
+    pub fn update(&mut self, addr: Address, value: &MeterValue, rssi: Option<i16>) {
+        self.gauges.update(addr, value, rssi);
+    }
+
+    /// Replaces the file atomically, so the collector never reads a partially
+    /// written file.
+    pub fn write(&self) -> io::Result<()> {
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        fs::write(&tmp, self.gauges.render())?;
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prometheus::Gauges;
+    use bluer::Address;
+    use meterreader_models::{Celsius, Humidity, MeterValue, TempUnit};
+
+    #[test]
+    fn renders_gauges() {
+        let mut gauges = Gauges::default();
+        let addr = Address::new([0xd3, 0x4e, 0x07, 0x01, 0x02, 0x03]);
+        let value = |temperature| MeterValue {
+            temperature: Celsius(temperature),
+            humidity: Humidity(40),
+            battery: 100,
+            display_unit: TempUnit::Celsius,
+        };
+        gauges.update(addr, &value(24.9), Some(-70));
+        // Only the latest value is kept.
+        gauges.update(addr, &value(25.1), Some(-67));
+        // The signal strength is left out where it is unknown.
+        gauges.update(Address::any(), &value(19.5), None);
+
+        assert_eq!(
+            gauges.render(),
+            "# HELP meter_temperature_celsius Temperature in degrees Celsius\n\
+             # TYPE meter_temperature_celsius gauge\n\
+             meter_temperature_celsius{address=\"00:00:00:00:00:00\"} 19.5\n\
+             meter_temperature_celsius{address=\"D3:4E:07:01:02:03\"} 25.1\n\
+             # HELP meter_humidity_percent Relative humidity in percent\n\
+             # TYPE meter_humidity_percent gauge\n\
+             meter_humidity_percent{address=\"00:00:00:00:00:00\"} 40\n\
+             meter_humidity_percent{address=\"D3:4E:07:01:02:03\"} 40\n\
+             # HELP meter_battery_percent Battery level in percent\n\
+             # TYPE meter_battery_percent gauge\n\
+             meter_battery_percent{address=\"00:00:00:00:00:00\"} 100\n\
+             meter_battery_percent{address=\"D3:4E:07:01:02:03\"} 100\n\
+             # HELP meter_rssi_dbm Signal strength of the latest advertisement in dBm\n\
+             # TYPE meter_rssi_dbm gauge\n\
+             meter_rssi_dbm{address=\"D3:4E:07:01:02:03\"} -67\n"
+        );
+    }
+}
@@ -0,0 +1,192 @@
+//! Replays frames recorded in a file instead of talking to real meters.
+//!
+//! Every line of the file holds one frame as a type, the meter's address and
+//! the frame's bytes in hex, e.g.
+//!
+//! ```text
+//! # Lines starting with '#' are ignored.
+//! adv D3:4E:07:01:02:03 690064099828
+//! resp D3:4E:07:01:02:03 01620f8a1062010f13014000000000
+//! adv D3:4E:07:0A:0B:0C 7700e4 d34e070a0b0c2d0b0300069533
+//! ```
+//!
+//! `adv` frames are advertisement service data, followed by the manufacturer
+//! data for Outdoor Meters, which are printed when discovering. `resp` frames are the meter's responses to the commands it
+//! is sent, in the order the commands are sent.
+
+use bluer::Address;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+use std::io;
+use std::path::Path;
+
+use meterreader_models::{Command, MeterValue};
+
+use crate::{cli, error, output, run_command, warn_on_low_battery, Transport};
+
+/// The frames recorded for one meter.
+#[derive(Debug, Default, Eq, PartialEq)]
+struct Frames {
+    /// Service data and manufacturer data, if any, of advertisements.
+    adverts: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+    responses: VecDeque<Vec<u8>>,
+}
+
+/// Answers commands with the recorded responses of a meter.
+struct Replay {
+    addr: Address,
+    responses: VecDeque<Vec<u8>>,
+}
+
+impl Transport for Replay {
+    async fn exec(&mut self, _cmd: &Command) -> error::Result<Vec<u8>> {
+        self.responses.pop_front().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("No more responses of {} to replay", self.addr),
+            )
+            .into()
+        })
+    }
+
+    fn command_retries(&self) -> u32 {
+        0
+    }
+
+    fn address(&self) -> Address {
+        self.addr
+    }
+}
+
+/// Parses the frames in `content`, grouped by meter.
+fn parse(content: &str) -> Result<BTreeMap<Address, Frames>, String> {
+    let mut meters = BTreeMap::<Address, Frames>::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = |err: &str| format!("Line {}: {err}", i + 1);
+
+        let mut fields = line.split_whitespace();
+        let (Some(kind), Some(addr), Some(frame), extra, None) = (
+            fields.next(),
+            fields.next(),
+            fields.next(),
+            fields.next(),
+            fields.next(),
+        ) else {
+            return Err(invalid("expected a type, an address and a frame"));
+        };
+        let addr = addr
+            .parse()
+            .map_err(|_| invalid("invalid Bluetooth address"))?;
+        let frame = hex::decode(frame).map_err(|_| invalid("invalid hex frame"))?;
+
+        let frames = meters.entry(addr).or_default();
+        match (kind, extra) {
+            ("adv", extra) => {
+                let manufacturer_data = extra
+                    .map(hex::decode)
+                    .transpose()
+                    .map_err(|_| invalid("invalid hex manufacturer data"))?;
+                frames.adverts.push((frame, manufacturer_data));
+            }
+            ("resp", None) => frames.responses.push_back(frame),
+            ("resp", Some(_)) => return Err(invalid("expected an address and a frame")),
+            _ => return Err(invalid("unknown frame type, expected adv or resp")),
+        }
+    }
+    Ok(meters)
+}
+
+/// Runs the command given on the command line against the frames recorded in
+/// the file at `path`.
+pub async fn run(
+    path: &Path,
+    args: &cli::Args,
+    printer: &RefCell<output::Printer>,
+) -> error::Result<()> {
+    let content = std::fs::read_to_string(path)?;
+    let meters = parse(&content).map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Could not read frames from {}: {err}", path.display()),
+        )
+    })?;
+
+    for (addr, frames) in meters.into_iter().filter(|(addr, _)| args.wants(*addr)) {
+        if let cli::Action::Discover { .. } = args.action {
+            for (data, manufacturer_data) in &frames.adverts {
+                if let Ok(value) =
+                    MeterValue::from_advertisement(data, manufacturer_data.as_deref())
+                {
+                    warn_on_low_battery(addr, &value);
+                    printer.borrow_mut().print_value(addr, None, &value, None)?;
+                }
+            }
+        } else {
+            let mut replay = Replay {
+                addr,
+                responses: frames.responses,
+            };
+            run_command(&mut replay, addr, args, printer).await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::replay::{parse, Frames, Replay};
+    use crate::MeterCommands;
+    use bluer::Address;
+
+    const ADDR: Address = Address([0xd3, 0x4e, 0x07, 0x01, 0x02, 0x03]);
+
+    #[test]
+    fn parses_frames() {
+        let meters = parse(
+            "# A comment\n\
+             adv D3:4E:07:01:02:03 690064099828\n\
+             \n\
+             resp D3:4E:07:01:02:03 01\n\
+             resp D3:4E:07:01:02:03 0102\n\
+             adv D3:4E:07:01:02:03 7700e4 0b0c\n",
+        )
+        .unwrap();
+        assert_eq!(
+            meters[&ADDR],
+            Frames {
+                adverts: vec![
+                    (vec![0x69, 0, 0x64, 0x09, 0x98, 0x28], None),
+                    (vec![0x77, 0, 0xe4], Some(vec![0x0b, 0x0c])),
+                ],
+                responses: [vec![1], vec![1, 2]].into(),
+            }
+        );
+
+        assert_eq!(
+            parse("adv D3:4E:07:01:02:03 6900zz"),
+            Err("Line 1: invalid hex frame".to_string())
+        );
+        assert_eq!(
+            parse("\nnotify D3:4E:07:01:02:03 01"),
+            Err("Line 2: unknown frame type, expected adv or resp".to_string())
+        );
+        assert!(parse("adv D3:4E:07:01:02:03").is_err());
+        assert!(parse("resp D3:4E:07:01:02:03 01 02").is_err());
+    }
+
+    #[tokio::test]
+    async fn replays_responses_in_order() {
+        let mut replay = Replay {
+            addr: ADDR,
+            responses: [vec![1, 25, 2]].into(),
+        };
+        let info = replay.read_device_info().await.unwrap();
+        assert_eq!(info.firmware, "2.5");
+        // Running out of responses is an error, not a hang.
+        assert!(replay.read_device_info().await.is_err());
+    }
+}
@@ -0,0 +1,89 @@
+use http_body_util::Full;
+use hyper::body::Bytes;
+use hyper::{header, Method, Request, Response, StatusCode};
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use tracing::warn;
+
+use crate::prometheus::Gauges;
+
+/// Serves the latest readings on `/metrics` to every connection accepted by
+/// `listener`, until the future is dropped.
+pub async fn serve(listener: TcpListener, gauges: Arc<Mutex<Gauges>>) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(err) => {
+                warn!("Could not accept connection: {err}");
+                continue;
+            }
+        };
+        let gauges = gauges.clone();
+        let service = hyper::service::service_fn(move |request| {
+            let response = respond(&request, &gauges);
+            async move { Ok::<_, Infallible>(response) }
+        });
+        tokio::spawn(async move {
+            let io = hyper_util::rt::TokioIo::new(stream);
+            if let Err(err) = hyper::server::conn::http1::Builder::new()
+                .serve_connection(io, service)
+                .await
+            {
+                warn!("Could not serve metrics: {err}");
+            }
+        });
+    }
+}
+
+fn respond<B>(request: &Request<B>, gauges: &Mutex<Gauges>) -> Response<Full<Bytes>> {
+    let mut response = Response::default();
+    if request.method() != Method::GET {
+        *response.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+    } else if request.uri().path() == "/metrics" {
+        let metrics = gauges.lock().unwrap().render();
+        *response.body_mut() = Full::new(metrics.into());
+        response.headers_mut().insert(
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("text/plain; version=0.0.4"),
+        );
+    } else {
+        *response.status_mut() = StatusCode::NOT_FOUND;
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prometheus::Gauges;
+    use crate::serve::respond;
+    use hyper::{Method, Request, StatusCode};
+    use std::sync::Mutex;
+
+    #[test]
+    fn serves_only_metrics() {
+        let gauges = Mutex::new(Gauges::default());
+        let request = |method, path| {
+            Request::builder()
+                .method(method)
+                .uri(path)
+                .body(())
+                .unwrap()
+        };
+
+        let response = respond(&request(Method::GET, "/metrics"), &gauges);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()["content-type"],
+            "text/plain; version=0.0.4"
+        );
+        assert_eq!(
+            respond(&request(Method::GET, "/"), &gauges).status(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            respond(&request(Method::POST, "/metrics"), &gauges).status(),
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use bluer::Address;
+use rusqlite::{params, Connection};
+use std::path::Path;
+
+use meterreader_models::{MeterSampleValue, MeterValue};
+
+/// Stores readings in a `SQLite` database. Temperatures are always stored in
+/// degrees Celsius, timestamps as Unix timestamps.
+pub struct Database {
+    conn: Connection,
+}
+
+impl Database {
+    pub fn open(path: &Path) -> rusqlite::Result<Database> {
+        Database::init(Connection::open(path)?)
+    }
+
+    fn init(conn: Connection) -> rusqlite::Result<Database> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS readings (
+                address TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                temperature REAL NOT NULL,
+                humidity INTEGER NOT NULL,
+                battery INTEGER,
+                PRIMARY KEY (address, timestamp)
+            )",
+        )?;
+        Ok(Database { conn })
+    }
+
+    pub fn insert_value(
+        &mut self,
+        addr: Address,
+        timestamp: i64,
+        value: &MeterValue,
+    ) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO readings VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                addr.to_string(),
+                timestamp,
+                value.temperature.0,
+                value.humidity.0,
+                value.battery
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Inserts historic samples in a single transaction. Samples that are
+    /// already stored, e.g. from an earlier dump, are skipped.
+    pub fn insert_samples(
+        &mut self,
+        addr: Address,
+        samples: &[(i64, MeterSampleValue)],
+    ) -> rusqlite::Result<()> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT OR IGNORE INTO readings (address, timestamp, temperature, humidity)
+                VALUES (?1, ?2, ?3, ?4)",
+            )?;
+            let addr = addr.to_string();
+            for (timestamp, sample) in samples {
+                stmt.execute(params![
+                    addr,
+                    timestamp,
+                    sample.temperature.0,
+                    sample.humidity.0
+                ])?;
+            }
+        }
+        tx.commit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sqlite::Database;
+    use bluer::Address;
+    use meterreader_models::{
+        Celsius, Humidity, MeterSampleValue, MeterValue, SampleFlags, TempUnit,
+    };
+    use rusqlite::Connection;
+
+    #[test]
+    fn skips_duplicate_readings() {
+        let mut db = Database::init(Connection::open_in_memory().unwrap()).unwrap();
+        let addr = Address::new([0xd3, 0x4e, 0x07, 0x01, 0x02, 0x03]);
+        let samples = [
+            (
+                1000,
+                MeterSampleValue {
+                    temperature: Celsius(24.9),
+                    humidity: Humidity(40),
+                    flags: SampleFlags::default(),
+                },
+            ),
+            (
+                1120,
+                MeterSampleValue {
+                    temperature: Celsius(25.1),
+                    humidity: Humidity(41),
+                    flags: SampleFlags::default(),
+                },
+            ),
+        ];
+
+        db.insert_samples(addr, &samples).unwrap();
+        db.insert_samples(addr, &samples).unwrap();
+        let value = MeterValue {
+            temperature: Celsius(25.3),
+            humidity: Humidity(42),
+            battery: 100,
+            display_unit: TempUnit::Celsius,
+        };
+        db.insert_value(addr, 1240, &value).unwrap();
+
+        let count: i64 = db
+            .conn
+            .query_row("SELECT COUNT(*) FROM readings", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 3);
+        let battery: Option<u8> = db
+            .conn
+            .query_row(
+                "SELECT battery FROM readings WHERE timestamp = 1240",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(battery, Some(100));
+    }
+}
@@ -0,0 +1,87 @@
+use core::fmt;
+
+/// How full a meter's battery is, for alerting on meters that need new
+/// batteries.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BatteryState {
+    Full,
+    Good,
+    Low,
+    Critical,
+}
+
+impl fmt::Display for BatteryState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BatteryState::Full => "full",
+            BatteryState::Good => "good",
+            BatteryState::Low => "low",
+            BatteryState::Critical => "critical",
+        })
+    }
+}
+
+/// The battery percentages at which the [`BatteryState`] changes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BatteryThresholds {
+    /// The battery is full at or above this percentage.
+    pub full: u8,
+    /// The battery is low below this percentage.
+    pub low: u8,
+    /// The battery is critical below this percentage.
+    pub critical: u8,
+}
+
+impl Default for BatteryThresholds {
+    fn default() -> BatteryThresholds {
+        BatteryThresholds {
+            full: 90,
+            low: 20,
+            critical: 10,
+        }
+    }
+}
+
+impl BatteryThresholds {
+    /// Returns the state of a battery at `percent`.
+    #[must_use]
+    pub fn classify(&self, percent: u8) -> BatteryState {
+        if percent < self.critical {
+            BatteryState::Critical
+        } else if percent < self.low {
+            BatteryState::Low
+        } else if percent < self.full {
+            BatteryState::Good
+        } else {
+            BatteryState::Full
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BatteryState, BatteryThresholds};
+
+    #[test]
+    fn classifies_at_boundaries() {
+        let thresholds = BatteryThresholds::default();
+        for (percent, state) in [
+            (0, BatteryState::Critical),
+            (9, BatteryState::Critical),
+            (10, BatteryState::Low),
+            (19, BatteryState::Low),
+            (20, BatteryState::Good),
+            (89, BatteryState::Good),
+            (90, BatteryState::Full),
+            (100, BatteryState::Full),
+        ] {
+            assert_eq!(thresholds.classify(percent), state, "{percent}%");
+        }
+
+        let thresholds = BatteryThresholds {
+            low: 30,
+            ..thresholds
+        };
+        assert_eq!(thresholds.classify(25), BatteryState::Low);
+    }
+}
@@ -0,0 +1,338 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::{ResponseCode, TempUnit};
+
+const CMD_READ_DEVICE_INFO: u8 = 2;
+const CMD_SET_TIME: u8 = 5;
+const CMD_SET_DISPLAY_UNIT: u8 = 6;
+const CMD_READ_NAME: u8 = 7;
+const CMD_SET_NAME: u8 = 8;
+const CMD_READ_ALARM_THRESHOLDS: u8 = 9;
+const CMD_READ_INDEX_INFO: u8 = 59;
+const CMD_READ_SAMPLE_INFO: u8 = 60;
+const CMD_CLEAR_HISTORY: u8 = 61;
+
+/// A command frame to be written to a meter.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Command {
+    code: u8,
+    bytes: Vec<u8>,
+    response_len: Option<usize>,
+}
+
+impl Command {
+    /// The longest name in bytes a meter accepts.
+    pub const MAX_NAME_LEN: usize = 20;
+
+    fn new(code: u8, payload: &[u8], response_len: Option<usize>) -> Command {
+        let mut bytes = Vec::with_capacity(3 + payload.len());
+        bytes.push(0x57);
+        bytes.push(if code > 0x0f { 0x0f } else { 0 });
+        bytes.push(code);
+        bytes.extend_from_slice(payload);
+        Command {
+            code,
+            bytes,
+            response_len,
+        }
+    }
+
+    #[must_use]
+    pub fn read_device_info() -> Command {
+        Command::new(CMD_READ_DEVICE_INFO, &[], Some(3))
+    }
+
+    /// Reads the name the meter is configured with.
+    #[must_use]
+    pub fn read_name() -> Command {
+        Command::new(CMD_READ_NAME, &[], None)
+    }
+
+    /// Sets the name of the meter, or returns `None` if `name` is longer than
+    /// [`Command::MAX_NAME_LEN`] bytes.
+    #[must_use]
+    pub fn set_name(name: &str) -> Option<Command> {
+        let len = u8::try_from(name.len())
+            .ok()
+            .filter(|&len| usize::from(len) <= Command::MAX_NAME_LEN)?;
+        let mut payload = vec![len];
+        payload.extend_from_slice(name.as_bytes());
+        Some(Command::new(CMD_SET_NAME, &payload, Some(1)))
+    }
+
+    /// Reads the thresholds of the meter's temperature and humidity alarms.
+    #[must_use]
+    pub fn read_alarm_thresholds() -> Command {
+        Command::new(CMD_READ_ALARM_THRESHOLDS, &[], Some(7))
+    }
+
+    #[must_use]
+    pub fn read_index_info() -> Command {
+        Command::read_section_index_info(0)
+    }
+
+    /// Reads the index info of the given section of the meter's log, which
+    /// is split into several once it has wrapped around.
+    #[must_use]
+    pub fn read_section_index_info(section: u8) -> Command {
+        Command::new(CMD_READ_INDEX_INFO, &[section], Some(13))
+    }
+
+    /// Reads `count` samples, starting at sample `index`.
+    #[must_use]
+    pub fn read_samples(index: u16, count: u8) -> Command {
+        Command::read_section_samples(0, index, count)
+    }
+
+    /// Reads `count` samples of the given section, starting at sample
+    /// `index`.
+    #[must_use]
+    pub fn read_section_samples(section: u8, index: u16, count: u8) -> Command {
+        let [index_high, index_low] = index.to_be_bytes();
+        // Every 5 bytes after the status byte hold two samples.
+        let response_len = 1 + usize::from(count).div_ceil(2) * 5;
+        Command::new(
+            CMD_READ_SAMPLE_INFO,
+            &[section, index_high, index_low, count],
+            Some(response_len),
+        )
+    }
+
+    /// Deletes all samples stored in the meter's log, which can't be undone.
+    #[must_use]
+    pub fn clear_history() -> Command {
+        Command::new(CMD_CLEAR_HISTORY, &[], Some(1))
+    }
+
+    /// Sets the meter's clock to the given Unix timestamp.
+    #[must_use]
+    pub fn set_time(timestamp: i64) -> Command {
+        let mut payload = vec![3, 0];
+        payload.extend_from_slice(&timestamp.to_be_bytes());
+        Command::new(CMD_SET_TIME, &payload, Some(1))
+    }
+
+    /// Sets the unit the meter's display shows temperatures in.
+    #[must_use]
+    pub fn set_display_unit(unit: TempUnit) -> Command {
+        let flag = match unit {
+            TempUnit::Celsius => 0,
+            TempUnit::Fahrenheit => 1,
+        };
+        Command::new(CMD_SET_DISPLAY_UNIT, &[flag], Some(1))
+    }
+
+    /// Protects the command with the password set on the meter.
+    ///
+    /// A protected frame has the password flag set in its second byte and
+    /// carries the CRC-32 of the password, big-endian, right after it.
+    #[must_use]
+    pub fn with_password(mut self, password: &[u8]) -> Command {
+        self.bytes[1] |= 0x10;
+        let crc = crc32(password).to_be_bytes();
+        self.bytes.splice(2..2, crc);
+        self
+    }
+
+    #[must_use]
+    pub fn code(&self) -> u8 {
+        self.code
+    }
+
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Returns the length of a successful response, if it is known. Only
+    /// responses to reading samples have exactly this length, others may
+    /// be longer.
+    #[must_use]
+    pub fn response_len(&self) -> Option<usize> {
+        self.response_len
+    }
+
+    /// Returns whether `response` is the complete response to this command.
+    ///
+    /// Large responses can be split across several notifications, so these
+    /// need to be read until the expected length is reached. Error responses
+    /// only consist of the status byte and are complete right away, as is any
+    /// response of unknown length.
+    #[must_use]
+    pub fn is_complete(&self, response: &[u8]) -> bool {
+        match (ResponseCode::from_response(response), self.response_len) {
+            (None, _) => false,
+            (Some(code), Some(len)) => !code.is_ok() || response.len() >= len,
+            (Some(_), None) => true,
+        }
+    }
+
+    /// Returns whether a complete `response` can be the response to this
+    /// command.
+    ///
+    /// Responses only consist of the status byte and the payload, without
+    /// echoing the command they answer, so their length is the only way to
+    /// tell a notification left over from another command apart. Successful
+    /// responses have to be at least as long as expected, as newer firmware
+    /// might append bytes, and responses to reading samples exactly as long,
+    /// as a different length means a different number of samples. Names
+    /// vary in length, so any response fits reading them.
+    #[must_use]
+    pub fn fits(&self, response: &[u8]) -> bool {
+        match (ResponseCode::from_response(response), self.response_len) {
+            (Some(code), Some(len)) if code.is_ok() => {
+                if self.code == CMD_READ_SAMPLE_INFO {
+                    response.len() == len
+                } else {
+                    response.len() >= len
+                }
+            }
+            _ => true,
+        }
+    }
+}
+
+/// Computes the CRC-32 (IEEE) checksum of `data`.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 0 {
+                crc >> 1
+            } else {
+                (crc >> 1) ^ 0xedb8_8320
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::crc32;
+    use crate::{Command, TempUnit};
+
+    #[test]
+    fn builds_read_device_info() {
+        assert_eq!(Command::read_device_info().as_bytes(), &[0x57, 0, 2]);
+    }
+
+    #[test]
+    fn builds_read_name() {
+        assert_eq!(Command::read_name().as_bytes(), &[0x57, 0, 7]);
+    }
+
+    #[test]
+    fn builds_set_name() {
+        assert_eq!(
+            Command::set_name("Garage").unwrap().as_bytes(),
+            &[0x57, 0, 8, 6, b'G', b'a', b'r', b'a', b'g', b'e']
+        );
+        assert!(Command::set_name(&"x".repeat(Command::MAX_NAME_LEN)).is_some());
+        assert!(Command::set_name(&"x".repeat(Command::MAX_NAME_LEN + 1)).is_none());
+    }
+
+    #[test]
+    fn builds_read_alarm_thresholds() {
+        assert_eq!(Command::read_alarm_thresholds().as_bytes(), &[0x57, 0, 9]);
+    }
+
+    #[test]
+    fn builds_read_index_info() {
+        assert_eq!(Command::read_index_info().as_bytes(), &[0x57, 0x0f, 59, 0]);
+        assert_eq!(
+            Command::read_section_index_info(2).as_bytes(),
+            &[0x57, 0x0f, 59, 2]
+        );
+    }
+
+    #[test]
+    fn builds_read_samples() {
+        assert_eq!(
+            Command::read_samples(1026, 6).as_bytes(),
+            &[0x57, 0x0f, 60, 0, 4, 2, 6]
+        );
+        assert_eq!(
+            Command::read_section_samples(1, 12, 6).as_bytes(),
+            &[0x57, 0x0f, 60, 1, 0, 12, 6]
+        );
+    }
+
+    #[test]
+    fn builds_clear_history() {
+        assert_eq!(Command::clear_history().as_bytes(), &[0x57, 0x0f, 61]);
+    }
+
+    #[test]
+    fn builds_set_time() {
+        assert_eq!(
+            Command::set_time(1_637_924_839).as_bytes(),
+            &[0x57, 0, 5, 3, 0, 0, 0, 0, 0, 97, 160, 191, 231]
+        );
+    }
+
+    #[test]
+    fn builds_set_display_unit() {
+        assert_eq!(
+            Command::set_display_unit(TempUnit::Celsius).as_bytes(),
+            &[0x57, 0, 6, 0]
+        );
+        assert_eq!(
+            Command::set_display_unit(TempUnit::Fahrenheit).as_bytes(),
+            &[0x57, 0, 6, 1]
+        );
+    }
+
+    #[test]
+    fn builds_password_protected_commands() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(
+            Command::read_device_info()
+                .with_password(b"1234")
+                .as_bytes(),
+            &[0x57, 0x10, 0x9b, 0xe3, 0xe0, 0xa3, 2]
+        );
+        assert_eq!(
+            Command::read_index_info().with_password(b"1234").as_bytes(),
+            &[0x57, 0x1f, 0x9b, 0xe3, 0xe0, 0xa3, 59, 0]
+        );
+    }
+
+    #[test]
+    fn waits_for_complete_responses() {
+        let cmd = Command::read_samples(0, 6);
+        assert_eq!(cmd.response_len(), Some(16));
+        assert!(!cmd.is_complete(&[]));
+        assert!(!cmd.is_complete(&[1, 152, 40, 119, 152, 40]));
+        assert!(cmd.is_complete(&[1; 16]));
+        // An error response is never going to get any longer.
+        assert!(cmd.is_complete(&[3]));
+
+        assert!(!Command::read_device_info().is_complete(&[1, 42]));
+        assert!(Command::read_name().is_complete(&[1, 42]));
+    }
+
+    #[test]
+    fn rejects_responses_of_other_lengths() {
+        let cmd = Command::read_samples(0, 2);
+        assert!(cmd.fits(&[1, 152, 40, 119, 152, 40]));
+        assert!(!cmd.fits(&[1, 97, 160, 191, 231, 97, 162, 162, 63, 4, 6, 0, 120]));
+        assert!(cmd.fits(&[3]));
+        assert!(Command::read_device_info().fits(&[1, 25, 2, 0]));
+    }
+
+    #[test]
+    fn accepts_longer_responses_unless_reading_samples() {
+        let cmd = Command::read_alarm_thresholds();
+        assert!(cmd.fits(&[1, 0, 30, 10, 60, 20, 1]));
+        assert!(cmd.fits(&[1, 0, 30, 10, 60, 20, 1, 0, 0]));
+        assert!(cmd.fits(&[1, 0, 30, 10, 60, 20, 1, 0, 0, 0, 0]));
+        assert!(!cmd.fits(&[1, 0, 30, 10]));
+        assert!(Command::set_time(0).fits(&[1, 0]));
+        assert!(!Command::read_device_info().fits(&[1]));
+        assert!(Command::read_name().fits(&[1]));
+        assert!(!Command::read_samples(0, 2).fits(&[1, 152, 40, 119, 152, 40, 0]));
+    }
+}
@@ -0,0 +1,91 @@
+//! Decodes a meter's history from raw responses captured elsewhere, without
+//! talking to the meter.
+
+use alloc::vec::Vec;
+use chrono::{DateTime, TimeZone, Utc};
+
+use crate::{MeterSampleValue, MeterSectionInfo};
+
+/// Decodes the responses to reading a section of a meter's log block by
+/// block, starting at its first sample, into timestamped samples, oldest
+/// first.
+///
+/// Gaps in the log and samples past the end of the section are left out. A
+/// block that can't be decoded is skipped, along with the samples its length
+/// suggests it held, so the samples of later blocks keep their timestamps.
+#[must_use]
+pub fn decode_history(
+    section: &MeterSectionInfo,
+    blocks: &[Vec<u8>],
+) -> Vec<(DateTime<Utc>, MeterSampleValue)> {
+    let mut history = Vec::with_capacity(usize::from(section.data_length));
+    let mut index = 0;
+    for block in blocks {
+        let samples = MeterSampleValue::from_response(block).unwrap_or_else(|_| {
+            // Every 5 bytes after the status byte hold two samples.
+            alloc::vec![None; block.len().saturating_sub(1) / 5 * 2]
+        });
+        for sample in samples {
+            if index >= section.data_length {
+                return history;
+            }
+            if let Some(sample) = sample {
+                let time = Utc.timestamp_opt(section.timestamp(index), 0).unwrap();
+                history.push((time, sample));
+            }
+            index += 1;
+        }
+    }
+    history
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decode::decode_history;
+    use crate::{Celsius, Humidity, MeterSampleValue, MeterSectionInfo, SampleFlags};
+    use alloc::vec;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn decodes_history_from_blocks() {
+        let section = MeterSectionInfo::from_response(&[
+            1, 97, 160, 191, 231, 97, 160, 193, 79, 0, 4, 0, 120,
+        ])
+        .unwrap();
+        let sample = MeterSampleValue {
+            temperature: Celsius(24.7),
+            humidity: Humidity(40),
+            flags: SampleFlags::default(),
+        };
+
+        // The second pair is a gap.
+        let blocks = vec![vec![1, 152, 40, 119, 152, 40, 255, 255, 255, 255, 255]];
+        assert_eq!(
+            decode_history(&section, &blocks),
+            [
+                (
+                    Utc.with_ymd_and_hms(2021, 11, 26, 11, 7, 19).unwrap(),
+                    sample
+                ),
+                (
+                    Utc.with_ymd_and_hms(2021, 11, 26, 11, 9, 19).unwrap(),
+                    sample
+                ),
+            ]
+        );
+
+        // A rejected block keeps the samples after it in place, and samples
+        // past the end of the section are dropped.
+        let blocks = vec![
+            vec![1, 152, 40, 119, 152, 40, 152, 40, 119, 152],
+            vec![1, 152, 40, 119, 152, 40],
+            vec![1, 152, 40, 119, 152, 40],
+        ];
+        let history = decode_history(&section, &blocks);
+        assert_eq!(history.len(), 2);
+        assert_eq!(
+            history[0].0,
+            Utc.with_ymd_and_hms(2021, 11, 26, 11, 11, 19).unwrap()
+        );
+    }
+}
@@ -0,0 +1,47 @@
+//! Float functions that `core` doesn't provide.
+//!
+//! These use the standard library when it's available and fall back to
+//! `libm` otherwise, which the `libm` feature has to be enabled for.
+
+#[cfg(not(any(feature = "std", feature = "libm")))]
+compile_error!("meterreader_models needs either the std or the libm feature");
+
+#[cfg(feature = "std")]
+pub(crate) fn round(x: f32) -> f32 {
+    x.round()
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn round(x: f32) -> f32 {
+    libm::roundf(x)
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn ln(x: f32) -> f32 {
+    x.ln()
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn ln(x: f32) -> f32 {
+    libm::logf(x)
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn exp(x: f32) -> f32 {
+    x.exp()
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn exp(x: f32) -> f32 {
+    libm::expf(x)
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn sqrt(x: f64) -> f64 {
+    x.sqrt()
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn sqrt(x: f64) -> f64 {
+    libm::sqrt(x)
+}
@@ -1,4 +1,235 @@
-const RESPONSE_OK: u8 = 1;
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Range;
+
+mod battery;
+mod command;
+#[cfg(feature = "chrono")]
+pub mod decode;
+mod float;
+#[cfg(feature = "live")]
+pub mod live;
+pub mod stats;
+mod units;
+
+pub use battery::{BatteryState, BatteryThresholds};
+pub use command::Command;
+pub use units::{Celsius, Humidity, TempUnit};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ParseError {
+    TooShort {
+        expected: usize,
+        got: usize,
+    },
+    BadResponseCode(u8),
+    MisalignedLength(usize),
+    ZeroInterval,
+    BadChecksum,
+    /// A decoded value is outside of its valid range.
+    OutOfRange {
+        field: &'static str,
+        value: u8,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::TooShort { expected, got } => {
+                write!(f, "frame too short: expected {expected} bytes, got {got}")
+            }
+            ParseError::BadResponseCode(code) => write!(f, "unexpected response code {code}"),
+            ParseError::MisalignedLength(len) => write!(f, "misaligned frame length {len}"),
+            ParseError::ZeroInterval => write!(f, "sample interval is zero"),
+            ParseError::BadChecksum => write!(f, "checksum mismatch"),
+            ParseError::OutOfRange { field, value } => write!(f, "{field} {value} out of range"),
+        }
+    }
+}
+
+impl core::error::Error for ParseError {}
+
+/// The status byte a meter sends at the start of every command response.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ResponseCode {
+    Ok,
+    /// The meter is busy with another command; retrying later may succeed.
+    Busy,
+    /// The meter doesn't support the command.
+    Unsupported,
+    /// The meter rejected the command's parameters.
+    BadParam,
+    Unknown(u8),
+}
+
+impl ResponseCode {
+    #[must_use]
+    pub fn from_byte(code: u8) -> ResponseCode {
+        match code {
+            1 => ResponseCode::Ok,
+            2 => ResponseCode::BadParam,
+            3 => ResponseCode::Busy,
+            5 => ResponseCode::Unsupported,
+            code => ResponseCode::Unknown(code),
+        }
+    }
+
+    /// Returns the code at the start of a response, if there is one.
+    #[must_use]
+    pub fn from_response(data: &[u8]) -> Option<ResponseCode> {
+        data.first().copied().map(ResponseCode::from_byte)
+    }
+
+    #[must_use]
+    pub fn is_ok(self) -> bool {
+        self == ResponseCode::Ok
+    }
+}
+
+impl fmt::Display for ResponseCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResponseCode::Ok => write!(f, "ok"),
+            ResponseCode::Busy => write!(f, "busy"),
+            ResponseCode::Unsupported => write!(f, "unsupported command"),
+            ResponseCode::BadParam => write!(f, "bad parameter"),
+            ResponseCode::Unknown(code) => write!(f, "unknown response code {code}"),
+        }
+    }
+}
+
+/// Fails with [`ParseError::BadResponseCode`] if a response starts with
+/// anything but [`ResponseCode::Ok`].
+///
+/// This is checked before the length, as error responses usually consist of
+/// the status byte alone.
+fn check_response_code(data: &[u8]) -> Result<(), ParseError> {
+    match ResponseCode::from_response(data) {
+        Some(code) if !code.is_ok() => Err(ParseError::BadResponseCode(data[0])),
+        _ => Ok(()),
+    }
+}
+
+/// Decodes a temperature from its whole degrees and tenths.
+///
+/// The high bit of `degrees` is the sign, which is *set* for temperatures
+/// above or at zero. Only the lower 4 bits of `tenths` are used.
+fn decode_temperature(degrees: u8, tenths: u8) -> f32 {
+    let magnitude = f32::from(degrees & 0x7f) + f32::from(tenths & 0xf) / 10.0;
+    if (degrees & 0x80) == 0 && magnitude != 0.0 {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
+
+/// Decodes a relative humidity from the lower 7 bits of `raw`, rejecting
+/// values above 100%.
+fn decode_humidity(raw: u8) -> Result<Humidity, ParseError> {
+    let humidity = raw & 0x7f;
+    if humidity > 100 {
+        return Err(ParseError::OutOfRange {
+            field: "humidity",
+            value: humidity,
+        });
+    }
+    Ok(Humidity(humidity))
+}
+
+/// Verifies a frame whose last byte is the 8-bit wrapping sum of all bytes
+/// before it.
+#[must_use]
+pub fn verify_checksum(data: &[u8]) -> bool {
+    match data.split_last() {
+        Some((checksum, payload)) => {
+            payload
+                .iter()
+                .fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+                == *checksum
+        }
+        None => false,
+    }
+}
+
+fn strip_checksum(data: &[u8]) -> Result<&[u8], ParseError> {
+    if verify_checksum(data) {
+        Ok(&data[..data.len() - 1])
+    } else {
+        Err(ParseError::BadChecksum)
+    }
+}
+
+/// Converts a temperature to degrees Fahrenheit, rounded to one decimal.
+#[must_use]
+pub fn celsius_to_fahrenheit(celsius: f32) -> f32 {
+    float::round((celsius * 9.0 / 5.0 + 32.0) * 10.0) / 10.0
+}
+
+/// Computes the dew point in degrees Celsius using the Magnus-Tetens
+/// approximation.
+///
+/// A relative humidity of 0% is treated as 1%, as the dew point isn't
+/// defined for completely dry air.
+#[must_use]
+pub fn dew_point(temperature: f32, humidity: u8) -> f32 {
+    const A: f32 = 17.62;
+    const B: f32 = 243.12;
+
+    let humidity = f32::from(humidity.max(1));
+    let gamma = float::ln(humidity / 100.0) + A * temperature / (B + temperature);
+    B * gamma / (A - gamma)
+}
+
+/// Computes the absolute humidity in grams of water vapor per cubic meter,
+/// from the saturation vapor pressure given by the Magnus formula.
+#[must_use]
+pub fn absolute_humidity(temperature: f32, humidity: u8) -> f32 {
+    let saturation_pressure = 6.112 * float::exp(17.67 * temperature / (temperature + 243.5));
+    saturation_pressure * f32::from(humidity) * 2.1674 / (273.15 + temperature)
+}
+
+/// Computes the heat index (apparent temperature) in degrees Celsius using
+/// the NOAA Rothfusz regression, falling back to the simple formula for mild
+/// conditions where the regression isn't valid.
+#[must_use]
+pub fn heat_index(temperature: f32, humidity: u8) -> f32 {
+    let t = f64::from(temperature) * 9.0 / 5.0 + 32.0;
+    let rh = f64::from(humidity);
+
+    let simple = 0.5 * (t + 61.0 + (t - 68.0) * 1.2 + rh * 0.094);
+    let fahrenheit = if f64::midpoint(simple, t) < 80.0 {
+        simple
+    } else {
+        let mut hi = -42.379 + 2.049_015_23 * t + 10.143_331_27 * rh
+            - 0.224_755_41 * t * rh
+            - 0.006_837_83 * t * t
+            - 0.054_817_17 * rh * rh
+            + 0.001_228_74 * t * t * rh
+            + 0.000_852_82 * t * rh * rh
+            - 0.000_001_99 * t * t * rh * rh;
+        if rh < 13.0 && (80.0..=112.0).contains(&t) {
+            hi -= (13.0 - rh) / 4.0 * float::sqrt((17.0 - (t - 95.0).abs()) / 17.0);
+        } else if rh > 85.0 && (80.0..=87.0).contains(&t) {
+            hi += (rh - 85.0) / 10.0 * ((87.0 - t) / 5.0);
+        }
+        hi
+    };
+
+    #[allow(clippy::cast_possible_truncation)]
+    let celsius = ((fahrenheit - 32.0) * 5.0 / 9.0) as f32;
+    celsius
+}
+
+/// How many samples `data_length` may differ from the length implied by the
+/// start and end times of a section for it to count as consistent.
+pub const SECTION_LENGTH_TOLERANCE: u32 = 2;
 
 #[derive(Debug, Eq, PartialEq)]
 pub struct MeterSectionInfo {
@@ -10,115 +241,778 @@ pub struct MeterSectionInfo {
 
 impl MeterSectionInfo {
     #![allow(clippy::missing_panics_doc)]
-    #[must_use]
-    pub fn from_response(data: &[u8]) -> Option<MeterSectionInfo> {
-        if data.len() < 13 || data[0] != RESPONSE_OK {
-            return None;
+    /// Parses the response to a read index info command.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ParseError`] if the response is truncated, not OK or
+    /// reports a zero sample interval.
+    pub fn from_response(data: &[u8]) -> Result<MeterSectionInfo, ParseError> {
+        check_response_code(data)?;
+        if data.len() < 13 {
+            return Err(ParseError::TooShort {
+                expected: 13,
+                got: data.len(),
+            });
         }
 
         let start_time = u32::from_be_bytes(data[1..5].try_into().unwrap());
         let end_time = u32::from_be_bytes(data[5..9].try_into().unwrap());
         let data_length = u16::from_be_bytes(data[9..11].try_into().unwrap());
         let interval = u16::from_be_bytes(data[11..13].try_into().unwrap());
+        if interval == 0 {
+            return Err(ParseError::ZeroInterval);
+        }
 
-        Some(MeterSectionInfo {
+        Ok(MeterSectionInfo {
             start_time,
             end_time,
             data_length,
             interval,
         })
     }
+
+    /// Returns the time the first sample of this section was taken.
+    #[cfg(feature = "chrono")]
+    #[must_use]
+    pub fn start(&self) -> chrono::DateTime<chrono::Utc> {
+        utc_time(self.start_time)
+    }
+
+    /// Returns the time the last sample of this section was taken.
+    #[cfg(feature = "chrono")]
+    #[must_use]
+    pub fn end(&self) -> chrono::DateTime<chrono::Utc> {
+        utc_time(self.end_time)
+    }
+
+    /// Returns whether `data_length` matches the number of samples expected
+    /// between `start_time` and `end_time`, give or take
+    /// [`SECTION_LENGTH_TOLERANCE`] samples.
+    ///
+    /// Meters have been seen to report a stale `data_length` after a reset,
+    /// which makes the computed sample timestamps meaningless.
+    #[must_use]
+    pub fn is_consistent(&self) -> bool {
+        if self.end_time < self.start_time {
+            return false;
+        }
+        let expected = (self.end_time - self.start_time) / u32::from(self.interval) + 1;
+        expected.abs_diff(u32::from(self.data_length)) <= SECTION_LENGTH_TOLERANCE
+    }
+
+    /// Returns the Unix timestamps of the last `sample_count` samples of this
+    /// section, oldest first.
+    pub fn timestamps(&self, sample_count: usize) -> impl Iterator<Item = i64> {
+        let data_length = usize::from(self.data_length);
+        let sample_count = sample_count.min(data_length);
+        let start_time = i64::from(self.start_time);
+        let interval = i64::from(self.interval);
+
+        (data_length - sample_count..data_length)
+            .map(move |i| start_time + i64::try_from(i).unwrap() * interval)
+    }
+
+    /// Returns the Unix timestamp of the sample at `index`.
+    #[must_use]
+    pub fn timestamp(&self, index: u16) -> i64 {
+        i64::from(self.start_time) + i64::from(index) * i64::from(self.interval)
+    }
+
+    /// Returns the indices of the samples taken between the Unix timestamps
+    /// `since` and `until` (both inclusive), clamped to the samples of this
+    /// section. A missing bound extends the range to the first or last sample.
+    #[must_use]
+    pub fn sample_range(&self, since: Option<i64>, until: Option<i64>) -> Range<u16> {
+        let start_time = i64::from(self.start_time);
+        let interval = i64::from(self.interval);
+        let data_length = i64::from(self.data_length);
+
+        let first = since.map_or(0, |since| {
+            (since - start_time + interval - 1).div_euclid(interval)
+        });
+        let end = until.map_or(data_length, |until| {
+            (until - start_time).div_euclid(interval) + 1
+        });
+        let first = first.clamp(0, data_length);
+        let end = end.clamp(first, data_length);
+        u16::try_from(first).unwrap()..u16::try_from(end).unwrap()
+    }
+
+    /// Returns the indices of the newest samples covering the last `seconds`
+    /// seconds of this section.
+    #[must_use]
+    pub fn last_samples(&self, seconds: i64) -> Range<u16> {
+        let wanted = u16::try_from(seconds.max(0) / i64::from(self.interval)).unwrap_or(u16::MAX);
+        self.data_length.saturating_sub(wanted)..self.data_length
+    }
+
+    /// Like [`MeterSectionInfo::from_response`], but expects a trailing
+    /// checksum byte (see [`verify_checksum`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ParseError::BadChecksum`] if the checksum doesn't match, or
+    /// any error [`MeterSectionInfo::from_response`] would return.
+    pub fn from_response_checked(data: &[u8]) -> Result<MeterSectionInfo, ParseError> {
+        MeterSectionInfo::from_response(strip_checksum(data)?)
+    }
 }
 
-#[derive(Debug, PartialEq)]
-pub struct MeterSampleValue {
-    pub temperature: f32,
-    pub humidity: u8,
+impl fmt::Display for MeterSectionInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} samples every {}s from {} to {}",
+            self.data_length,
+            self.interval,
+            format_timestamp(self.start_time),
+            format_timestamp(self.end_time)
+        )
+    }
 }
 
-impl MeterSampleValue {
+/// Converts a meter's Unix timestamp, which always fits chrono's range.
+#[cfg(feature = "chrono")]
+fn utc_time(timestamp: u32) -> chrono::DateTime<chrono::Utc> {
+    use chrono::TimeZone;
+    chrono::Utc.timestamp_opt(i64::from(timestamp), 0).unwrap()
+}
+
+#[cfg(feature = "chrono")]
+fn format_timestamp(timestamp: u32) -> String {
+    utc_time(timestamp)
+        .format("%Y-%m-%d %H:%M:%S UTC")
+        .to_string()
+}
+
+/// Without chrono, timestamps are printed as seconds since the epoch.
+#[cfg(not(feature = "chrono"))]
+fn format_timestamp(timestamp: u32) -> String {
+    timestamp.to_string()
+}
+
+/// Which of the two samples in each 5-byte group of a read sample info
+/// response are decoded.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum PairMode {
+    /// Both samples, one after the other.
+    #[default]
+    Both,
+    /// Only the first sample.
+    First,
+    /// The average of both samples, or the one that isn't a gap.
+    Average,
+}
+
+/// Marks the values of a historic sample that decoded, but look implausible,
+/// e.g. because of a corrupted frame.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SampleFlags(u8);
+
+impl SampleFlags {
+    /// The tenths of the temperature are above 9, or the temperature is
+    /// outside of what the meter's sensor can measure.
+    pub const SUSPECT_TEMPERATURE: SampleFlags = SampleFlags(1);
+    /// The unused high bit of the humidity is set.
+    pub const SUSPECT_HUMIDITY: SampleFlags = SampleFlags(2);
+
+    #[must_use]
+    pub fn contains(self, other: SampleFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    #[must_use]
+    pub fn union(self, other: SampleFlags) -> SampleFlags {
+        SampleFlags(self.0 | other.0)
+    }
+
+    #[must_use]
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
     #[must_use]
-    pub fn from_response(data: &[u8]) -> Option<Vec<MeterSampleValue>> {
-        if data.len() < 6 || data[0] != RESPONSE_OK || (data.len() - 1) % 5 != 0 {
-            return None;
+    pub fn suspect_temperature(self) -> bool {
+        self.contains(SampleFlags::SUSPECT_TEMPERATURE)
+    }
+
+    #[must_use]
+    pub fn suspect_humidity(self) -> bool {
+        self.contains(SampleFlags::SUSPECT_HUMIDITY)
+    }
+
+    /// Flags the raw bytes of a sample's temperature and humidity.
+    fn of(degrees: u8, tenths: u8, humidity: u8) -> SampleFlags {
+        let mut flags = SampleFlags::default();
+        let temperature = decode_temperature(degrees, tenths);
+        if tenths > 9 || !(SENSOR_MIN_TEMPERATURE..=SENSOR_MAX_TEMPERATURE).contains(&temperature) {
+            flags = flags.union(SampleFlags::SUSPECT_TEMPERATURE);
+        }
+        if humidity & 0x80 != 0 {
+            flags = flags.union(SampleFlags::SUSPECT_HUMIDITY);
         }
+        flags
+    }
+}
+
+/// The range of temperatures in degrees Celsius the sensors of meters can
+/// measure.
+const SENSOR_MIN_TEMPERATURE: f32 = -40.0;
+const SENSOR_MAX_TEMPERATURE: f32 = 85.0;
 
-        let mut result = Vec::with_capacity((data.len() - 1) / 6);
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeterSampleValue {
+    pub temperature: Celsius,
+    pub humidity: Humidity,
+    /// Values that decoded, but look implausible.
+    pub flags: SampleFlags,
+}
+
+impl MeterSampleValue {
+    /// Parses the response to a read sample info command.
+    ///
+    /// Samples in gaps of the meter's log hold no data and are returned as
+    /// `None`, so the other samples keep their positions.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ParseError`] if the response is truncated, not OK, its
+    /// payload isn't made up of complete 5-byte groups or a humidity is above
+    /// 100%.
+    pub fn from_response(data: &[u8]) -> Result<Vec<Option<MeterSampleValue>>, ParseError> {
+        MeterSampleValue::decode_blocks([data]).collect()
+    }
 
-        for i in (1..(data.len() - 1)).step_by(5) {
-            result.push(MeterSampleValue::first_value(&data[i..]));
-            result.push(MeterSampleValue::second_value(&data[i..]));
+    /// Like [`MeterSampleValue::from_response`], but decodes the samples of
+    /// each group as given by `mode`. Except with [`PairMode::Both`], this
+    /// returns one value per group, i.e. per two samples.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error [`MeterSampleValue::from_response`] would return.
+    pub fn from_response_with(
+        data: &[u8],
+        mode: PairMode,
+    ) -> Result<Vec<Option<MeterSampleValue>>, ParseError> {
+        if mode == PairMode::Both {
+            return MeterSampleValue::from_response(data);
         }
+        sample_groups(data)?
+            .as_chunks::<5>()
+            .0
+            .iter()
+            .map(|group| {
+                let first = MeterSampleValue::first_value(group)?;
+                if mode == PairMode::First {
+                    return Ok(first);
+                }
+                let second = MeterSampleValue::second_value(group)?;
+                Ok(match (first, second) {
+                    (Some(first), Some(second)) => Some(first.average(second)),
+                    (first, second) => first.or(second),
+                })
+            })
+            .collect()
+    }
 
-        Some(result)
+    /// Lazily decodes the samples in several responses to read sample info
+    /// commands, in order.
+    ///
+    /// A response that [`MeterSampleValue::from_response`] would reject as a
+    /// whole yields a single error in place of its samples, so decoding
+    /// continues with the next response.
+    pub fn decode_blocks<'a>(
+        blocks: impl IntoIterator<Item = &'a [u8]> + 'a,
+    ) -> impl Iterator<Item = Result<Option<MeterSampleValue>, ParseError>> + 'a {
+        blocks.into_iter().flat_map(|block| {
+            let (groups, err) = match sample_groups(block) {
+                Ok(payload) => (payload.as_chunks::<5>().0.iter(), None),
+                Err(err) => ([].iter(), Some(Err(err))),
+            };
+            err.into_iter().chain(groups.flat_map(|group| {
+                [
+                    MeterSampleValue::first_value(group),
+                    MeterSampleValue::second_value(group),
+                ]
+            }))
+        })
     }
 
-    fn first_value(data: &[u8]) -> MeterSampleValue {
-        assert!(data.len() >= 3);
+    /// Like [`MeterSampleValue::from_response`], but expects a trailing
+    /// checksum byte (see [`verify_checksum`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ParseError::BadChecksum`] if the checksum doesn't match, or
+    /// any error [`MeterSampleValue::from_response`] would return.
+    pub fn from_response_checked(data: &[u8]) -> Result<Vec<Option<MeterSampleValue>>, ParseError> {
+        MeterSampleValue::from_response(strip_checksum(data)?)
+    }
 
-        let mut temperature = f32::from(data[0] & 0x7f) + (f32::from((data[2] >> 4) & 0xf) / 10.0);
-        if (data[0] & 0x80) == 0 {
-            temperature = -temperature;
-        }
+    /// Returns the temperature in degrees Fahrenheit, rounded to one decimal.
+    #[must_use]
+    pub fn to_fahrenheit(&self) -> f32 {
+        self.temperature.as_fahrenheit()
+    }
+
+    /// Returns the dew point in degrees Celsius, see [`dew_point`].
+    #[must_use]
+    pub fn dew_point(&self) -> f32 {
+        dew_point(self.temperature.0, self.humidity.0)
+    }
+
+    /// Returns the heat index in degrees Celsius, see [`heat_index`].
+    #[must_use]
+    pub fn heat_index(&self) -> f32 {
+        heat_index(self.temperature.0, self.humidity.0)
+    }
 
-        let humidity = data[1] & 0x7f;
+    /// Returns the absolute humidity in g/m³, see [`absolute_humidity`].
+    #[must_use]
+    pub fn absolute_humidity(&self) -> f32 {
+        absolute_humidity(self.temperature.0, self.humidity.0)
+    }
 
+    /// Returns the average of two samples, rounded like the meter's own
+    /// readings.
+    fn average(self, other: MeterSampleValue) -> MeterSampleValue {
+        let humidity = (u16::from(self.humidity.0) + u16::from(other.humidity.0)).div_ceil(2);
         MeterSampleValue {
-            temperature,
-            humidity,
+            temperature: Celsius(
+                float::round((self.temperature.0 + other.temperature.0) * 5.0) / 10.0,
+            ),
+            humidity: Humidity(u8::try_from(humidity).unwrap_or(u8::MAX)),
+            flags: self.flags.union(other.flags),
         }
     }
 
-    fn second_value(data: &[u8]) -> MeterSampleValue {
-        assert!(data.len() >= 5);
+    fn first_value(data: &[u8]) -> Result<Option<MeterSampleValue>, ParseError> {
+        let &[degrees, humidity, tenths, ..] = data else {
+            return Err(ParseError::TooShort {
+                expected: 3,
+                got: data.len(),
+            });
+        };
+        if is_gap(degrees, humidity) {
+            return Ok(None);
+        }
+
+        let temperature = Celsius(decode_temperature(degrees, tenths >> 4));
+        let flags = SampleFlags::of(degrees, tenths >> 4, humidity);
+
+        let humidity = decode_humidity(humidity)?;
 
-        let mut temperature = f32::from(data[3] & 0x7f) + (f32::from(data[2] & 0xf) / 10.0);
-        if (data[3] & 0x80) == 0 {
-            temperature = -temperature;
+        Ok(Some(MeterSampleValue {
+            temperature,
+            humidity,
+            flags,
+        }))
+    }
+
+    fn second_value(data: &[u8]) -> Result<Option<MeterSampleValue>, ParseError> {
+        let &[_, _, tenths, degrees, humidity, ..] = data else {
+            return Err(ParseError::TooShort {
+                expected: 5,
+                got: data.len(),
+            });
+        };
+        if is_gap(degrees, humidity) {
+            return Ok(None);
         }
 
-        let humidity = data[4] & 0x7f;
+        let temperature = Celsius(decode_temperature(degrees, tenths & 0xf));
+        let flags = SampleFlags::of(degrees, tenths & 0xf, humidity);
 
-        MeterSampleValue {
+        let humidity = decode_humidity(humidity)?;
+
+        Ok(Some(MeterSampleValue {
             temperature,
             humidity,
+            flags,
+        }))
+    }
+}
+
+/// Returns whether the bytes of a sample are the all-ones sentinel a meter
+/// stores for gaps in its log, ignoring the sign bit of the temperature.
+fn is_gap(degrees: u8, humidity: u8) -> bool {
+    degrees & 0x7f == 0x7f && humidity & 0x7f == 0x7f
+}
+
+/// Checks a read sample info response and returns its payload of 5-byte
+/// groups, each holding two samples.
+fn sample_groups(data: &[u8]) -> Result<&[u8], ParseError> {
+    check_response_code(data)?;
+    if data.len() < 6 {
+        return Err(ParseError::TooShort {
+            expected: 6,
+            got: data.len(),
+        });
+    }
+    if !(data.len() - 1).is_multiple_of(5) {
+        return Err(ParseError::MisalignedLength(data.len()));
+    }
+    Ok(&data[1..])
+}
+
+impl fmt::Display for MeterSampleValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.temperature, self.humidity)
+    }
+}
+
+/// The meter models, identified by the first byte of their service data.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MeterModel {
+    /// The original Meter, device type `'T'`.
+    Meter,
+    /// The Meter Plus, device type `'i'`.
+    MeterPlus,
+    /// The Outdoor Meter (`WoIOTH`), device type `'w'`.
+    OutdoorMeter,
+}
+
+impl MeterModel {
+    #[must_use]
+    pub fn from_device_type(device_type: u8) -> Option<MeterModel> {
+        match device_type {
+            b'T' => Some(MeterModel::Meter),
+            b'i' => Some(MeterModel::MeterPlus),
+            b'w' => Some(MeterModel::OutdoorMeter),
+            _ => None,
         }
     }
 }
 
 #[derive(Debug, PartialEq)]
 pub struct MeterValue {
-    pub temperature: f32,
-    pub humidity: u8,
+    pub temperature: Celsius,
+    pub humidity: Humidity,
     pub battery: u8,
+    /// The unit the meter's display shows temperatures in.
+    pub display_unit: TempUnit,
 }
 
 impl MeterValue {
-    #[must_use]
-    pub fn from_data(data: &[u8]) -> Option<MeterValue> {
-        if data.len() != 6 || data[0] != 105 {
-            return None;
+    /// Parses the service data of an advertisement.
+    ///
+    /// The Meter and the Meter Plus share the same service data layout:
+    ///
+    /// | byte | content                                                                  |
+    /// |------|--------------------------------------------------------------------------|
+    /// | 0    | device type, see [`MeterModel`]                                          |
+    /// | 1    | status flags                                                             |
+    /// | 2    | battery in percent (lower 7 bits)                                        |
+    /// | 3    | tenths of the temperature (lower 4 bits)                                 |
+    /// | 4    | temperature in °C (lower 7 bits), high bit set if ≥ 0                    |
+    /// | 5    | humidity in percent (lower 7 bits), high bit set if the display shows °F |
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ParseError`] if the service data has the wrong length,
+    /// doesn't belong to a meter or reports a humidity above 100%.
+    pub fn from_data(data: &[u8]) -> Result<MeterValue, ParseError> {
+        if data.len() < 6 {
+            return Err(ParseError::TooShort {
+                expected: 6,
+                got: data.len(),
+            });
         }
-
-        let mut temperature = f32::from(data[4] & 0x7f) + (f32::from(data[3] & 0xf) / 10.0);
-        if (data[4] & 0x80) == 0 {
-            temperature = -temperature;
+        if data.len() != 6 {
+            return Err(ParseError::MisalignedLength(data.len()));
+        }
+        if !matches!(
+            MeterModel::from_device_type(data[0]),
+            Some(MeterModel::Meter | MeterModel::MeterPlus)
+        ) {
+            return Err(ParseError::BadResponseCode(data[0]));
         }
 
-        let humidity = data[5] & 0x7f;
+        let temperature = Celsius(decode_temperature(data[4], data[3]));
+
+        let humidity = decode_humidity(data[5])?;
         let battery = data[2] & 0x7f;
+        let display_unit = if data[5] & 0x80 == 0 {
+            TempUnit::Celsius
+        } else {
+            TempUnit::Fahrenheit
+        };
 
-        Some(MeterValue {
+        Ok(MeterValue {
             temperature,
             humidity,
             battery,
+            display_unit,
+        })
+    }
+
+    /// Parses the advertisement of an Outdoor Meter.
+    ///
+    /// Its service data only carries the battery level, in the lower 7 bits
+    /// of byte 2 as for the other meters. The reading is in the manufacturer
+    /// data instead, which follows the meter's address:
+    ///
+    /// | byte | content                                                                  |
+    /// |------|--------------------------------------------------------------------------|
+    /// | 0-5  | address of the meter                                                     |
+    /// | 6-9  | sequence number and flags                                                |
+    /// | 10   | tenths of the temperature (lower 4 bits)                                 |
+    /// | 11   | temperature in °C (lower 7 bits), high bit set if ≥ 0                    |
+    /// | 12   | humidity in percent (lower 7 bits), high bit set if the display shows °F |
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ParseError`] if either part is too short, the service data
+    /// doesn't belong to an Outdoor Meter or the humidity is above 100%.
+    pub fn from_outdoor_data(
+        service_data: &[u8],
+        manufacturer_data: &[u8],
+    ) -> Result<MeterValue, ParseError> {
+        let [device_type, _, battery, ..] = *service_data else {
+            return Err(ParseError::TooShort {
+                expected: 3,
+                got: service_data.len(),
+            });
+        };
+        if MeterModel::from_device_type(device_type) != Some(MeterModel::OutdoorMeter) {
+            return Err(ParseError::BadResponseCode(device_type));
+        }
+        let Some(&[tenths, degrees, humidity]) = manufacturer_data.get(10..13) else {
+            return Err(ParseError::TooShort {
+                expected: 13,
+                got: manufacturer_data.len(),
+            });
+        };
+
+        Ok(MeterValue {
+            temperature: Celsius(decode_temperature(degrees, tenths)),
+            humidity: decode_humidity(humidity)?,
+            battery: battery & 0x7f,
+            display_unit: if humidity & 0x80 == 0 {
+                TempUnit::Celsius
+            } else {
+                TempUnit::Fahrenheit
+            },
         })
     }
+
+    /// Parses an advertisement of any meter model, telling them apart by the
+    /// device type in the first byte of the service data.
+    ///
+    /// Only the Outdoor Meter needs the `manufacturer_data`, see
+    /// [`MeterValue::from_outdoor_data`].
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ParseError`] if the advertisement can't be parsed as the
+    /// model it claims to be from, or isn't from a meter at all.
+    pub fn from_advertisement(
+        service_data: &[u8],
+        manufacturer_data: Option<&[u8]>,
+    ) -> Result<MeterValue, ParseError> {
+        let model = service_data
+            .first()
+            .and_then(|&device_type| MeterModel::from_device_type(device_type));
+        if model == Some(MeterModel::OutdoorMeter) {
+            MeterValue::from_outdoor_data(service_data, manufacturer_data.unwrap_or_default())
+        } else {
+            MeterValue::from_data(service_data)
+        }
+    }
+
+    /// Returns the temperature in degrees Fahrenheit, rounded to one decimal.
+    #[must_use]
+    pub fn to_fahrenheit(&self) -> f32 {
+        self.temperature.as_fahrenheit()
+    }
+
+    /// Returns the state of the battery using the default thresholds.
+    #[must_use]
+    pub fn battery_state(&self) -> BatteryState {
+        BatteryThresholds::default().classify(self.battery)
+    }
+
+    /// Returns the dew point in degrees Celsius, see [`dew_point`].
+    #[must_use]
+    pub fn dew_point(&self) -> f32 {
+        dew_point(self.temperature.0, self.humidity.0)
+    }
+
+    /// Returns the heat index in degrees Celsius, see [`heat_index`].
+    #[must_use]
+    pub fn heat_index(&self) -> f32 {
+        heat_index(self.temperature.0, self.humidity.0)
+    }
+
+    /// Returns the absolute humidity in g/m³, see [`absolute_humidity`].
+    #[must_use]
+    pub fn absolute_humidity(&self) -> f32 {
+        absolute_humidity(self.temperature.0, self.humidity.0)
+    }
+}
+
+impl fmt::Display for MeterValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} bat{}%",
+            self.temperature, self.humidity, self.battery
+        )
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct DeviceInfo {
+    pub firmware: String,
+    pub hardware: u8,
+}
+
+impl DeviceInfo {
+    /// Parses the response to a read device info command.
+    ///
+    /// The firmware version is reported in tenths, i.e. a value of 25 stands
+    /// for firmware version 2.5.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ParseError`] if the response is truncated or not OK.
+    pub fn from_response(data: &[u8]) -> Result<DeviceInfo, ParseError> {
+        check_response_code(data)?;
+        if data.len() < 3 {
+            return Err(ParseError::TooShort {
+                expected: 3,
+                got: data.len(),
+            });
+        }
+
+        Ok(DeviceInfo {
+            firmware: format!("{}.{}", data[1] / 10, data[1] % 10),
+            hardware: data[2],
+        })
+    }
+}
+
+/// The thresholds of a meter's temperature and humidity alarms.
+#[derive(Debug, PartialEq)]
+pub struct AlarmThresholds {
+    pub temp_high: Celsius,
+    pub temp_low: Celsius,
+    pub hum_high: Humidity,
+    pub hum_low: Humidity,
+}
+
+impl AlarmThresholds {
+    /// Parses the response to a read alarm thresholds command.
+    ///
+    /// Temperatures are encoded like in advertisements:
+    ///
+    /// | byte | content                                                |
+    /// |------|--------------------------------------------------------|
+    /// | 0    | response code                                          |
+    /// | 1    | tenths of the high temperature (lower 4 bits)          |
+    /// | 2    | high temperature in °C (lower 7 bits), high bit if ≥ 0 |
+    /// | 3    | tenths of the low temperature (lower 4 bits)           |
+    /// | 4    | low temperature in °C (lower 7 bits), high bit if ≥ 0  |
+    /// | 5    | high humidity in percent                               |
+    /// | 6    | low humidity in percent                                |
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ParseError`] if the response is truncated, not OK or a
+    /// humidity is above 100%.
+    pub fn from_response(data: &[u8]) -> Result<AlarmThresholds, ParseError> {
+        check_response_code(data)?;
+        let &[_, high_tenths, high, low_tenths, low, hum_high, hum_low, ..] = data else {
+            return Err(ParseError::TooShort {
+                expected: 7,
+                got: data.len(),
+            });
+        };
+
+        Ok(AlarmThresholds {
+            temp_high: Celsius(decode_temperature(high, high_tenths)),
+            temp_low: Celsius(decode_temperature(low, low_tenths)),
+            hum_high: decode_humidity(hum_high)?,
+            hum_low: decode_humidity(hum_low)?,
+        })
+    }
+}
+
+/// The name a meter is configured with.
+#[derive(Debug, Eq, PartialEq)]
+pub struct DeviceName(pub String);
+
+impl DeviceName {
+    /// Parses the response to a read name command.
+    ///
+    /// The name is padded with trailing null bytes, which are dropped. Invalid
+    /// UTF-8 is replaced rather than rejected, so a garbled name can still be
+    /// seen and fixed.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ParseError`] if the response is empty or not OK.
+    pub fn from_response(data: &[u8]) -> Result<DeviceName, ParseError> {
+        check_response_code(data)?;
+        let Some((_, name)) = data.split_first() else {
+            return Err(ParseError::TooShort {
+                expected: 1,
+                got: 0,
+            });
+        };
+        let len = name
+            .iter()
+            .rposition(|&byte| byte != 0)
+            .map_or(0, |i| i + 1);
+        Ok(DeviceName(
+            String::from_utf8_lossy(&name[..len]).into_owned(),
+        ))
+    }
+}
+
+impl fmt::Display for DeviceName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{MeterSampleValue, MeterSectionInfo, MeterValue};
+    use crate::{
+        verify_checksum, AlarmThresholds, Celsius, DeviceInfo, DeviceName, Humidity, MeterModel,
+        MeterSampleValue, MeterSectionInfo, MeterValue, PairMode, ParseError, ResponseCode,
+        SampleFlags, TempUnit,
+    };
+
+    #[test]
+    fn parses_alarm_thresholds() {
+        // 35.5°C and -10.0°C, 70% and 30%
+        let response = [1, 5, 163, 0, 10, 70, 30];
+        assert_eq!(
+            AlarmThresholds::from_response(&response),
+            Ok(AlarmThresholds {
+                temp_high: Celsius(35.5),
+                temp_low: Celsius(-10.0),
+                hum_high: Humidity(70),
+                hum_low: Humidity(30),
+            })
+        );
+        assert_eq!(
+            AlarmThresholds::from_response(&response[..6]),
+            Err(ParseError::TooShort {
+                expected: 7,
+                got: 6
+            })
+        );
+        assert_eq!(
+            AlarmThresholds::from_response(&[1, 5, 163, 0, 10, 101, 30]),
+            Err(ParseError::OutOfRange {
+                field: "humidity",
+                value: 101
+            })
+        );
+    }
 
     #[test]
     fn parses_service_data() {
@@ -126,39 +1020,559 @@ mod tests {
         let result = MeterValue::from_data(&service_data);
         assert_eq!(
             result,
-            Some(MeterValue {
-                temperature: 24.9,
-                humidity: 40,
-                battery: 100
+            Ok(MeterValue {
+                temperature: Celsius(24.9),
+                humidity: Humidity(40),
+                battery: 100,
+                display_unit: TempUnit::Celsius,
+            })
+        );
+    }
+
+    #[test]
+    fn parses_display_unit() {
+        let celsius = MeterValue::from_data(&[105, 0, 228, 9, 152, 40]).unwrap();
+        assert_eq!(celsius.display_unit, TempUnit::Celsius);
+
+        let fahrenheit = MeterValue::from_data(&[105, 0, 228, 9, 152, 168]).unwrap();
+        assert_eq!(fahrenheit.display_unit, TempUnit::Fahrenheit);
+        // The flag shares its byte with the humidity, which must stay intact.
+        assert_eq!(fahrenheit.humidity, Humidity(40));
+        assert_eq!(fahrenheit.temperature, Celsius(24.9));
+    }
+
+    #[test]
+    fn parses_meter_service_data() {
+        let service_data = vec![b'T', 0, 228, 9, 152, 40];
+        assert_eq!(MeterModel::from_device_type(b'T'), Some(MeterModel::Meter));
+        assert_eq!(
+            MeterModel::from_device_type(b'i'),
+            Some(MeterModel::MeterPlus)
+        );
+        assert_eq!(
+            MeterValue::from_data(&service_data),
+            Ok(MeterValue {
+                temperature: Celsius(24.9),
+                humidity: Humidity(40),
+                battery: 100,
+                display_unit: TempUnit::Celsius,
             })
         );
     }
 
+    #[test]
+    fn computes_sample_ranges() {
+        let section_info = MeterSectionInfo {
+            start_time: 1000,
+            end_time: 0,
+            data_length: 100,
+            interval: 120,
+        };
+
+        assert_eq!(section_info.timestamp(2), 1240);
+        assert_eq!(section_info.sample_range(None, None), 0..100);
+        assert_eq!(section_info.sample_range(Some(1001), Some(1480)), 1..5);
+        assert_eq!(section_info.sample_range(Some(1120), Some(1359)), 1..3);
+        assert_eq!(section_info.sample_range(Some(0), Some(100_000)), 0..100);
+        assert_eq!(section_info.sample_range(Some(100_000), None), 100..100);
+        assert_eq!(section_info.sample_range(None, Some(0)), 0..0);
+        assert_eq!(section_info.last_samples(600), 95..100);
+        assert_eq!(section_info.last_samples(1_000_000), 0..100);
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn converts_section_times() {
+        use chrono::{TimeZone, Utc};
+
+        let section_info = MeterSectionInfo {
+            start_time: 1_637_924_839,
+            end_time: 1_638_048_319,
+            data_length: 1030,
+            interval: 120,
+        };
+        assert_eq!(
+            section_info.start(),
+            Utc.with_ymd_and_hms(2021, 11, 26, 11, 7, 19).unwrap()
+        );
+        assert_eq!(
+            section_info.end(),
+            Utc.with_ymd_and_hms(2021, 11, 27, 21, 25, 19).unwrap()
+        );
+
+        // Timestamps past 2038 don't wrap around.
+        let late = MeterSectionInfo {
+            end_time: u32::MAX,
+            ..section_info
+        };
+        assert_eq!(late.end().timestamp(), 4_294_967_295);
+    }
+
+    #[test]
+    fn checks_section_consistency() {
+        let mut section_info = MeterSectionInfo {
+            start_time: 1_637_924_839,
+            end_time: 1_638_048_319,
+            data_length: 1030,
+            interval: 120,
+        };
+        assert!(section_info.is_consistent());
+
+        section_info.data_length = 1028;
+        assert!(section_info.is_consistent());
+
+        // A stale length after a reset.
+        section_info.data_length = 12;
+        assert!(!section_info.is_consistent());
+
+        section_info.data_length = 1030;
+        section_info.end_time = 0;
+        assert!(!section_info.is_consistent());
+    }
+
+    #[test]
+    fn displays_values() {
+        let value = MeterValue {
+            temperature: Celsius(24.9),
+            humidity: Humidity(40),
+            battery: 100,
+            display_unit: TempUnit::Celsius,
+        };
+        assert_eq!(value.to_string(), "24.9°C 40% bat100%");
+
+        let sample = MeterSampleValue {
+            temperature: Celsius(-5.3),
+            humidity: Humidity(81),
+            flags: SampleFlags::default(),
+        };
+        assert_eq!(sample.to_string(), "-5.3°C 81%");
+
+        let section_info = MeterSectionInfo {
+            start_time: 1_637_924_839,
+            end_time: 1_638_048_439,
+            data_length: 1030,
+            interval: 120,
+        };
+        #[cfg(feature = "chrono")]
+        assert_eq!(
+            section_info.to_string(),
+            "1030 samples every 120s from 2021-11-26 11:07:19 UTC to 2021-11-27 21:27:19 UTC"
+        );
+        #[cfg(not(feature = "chrono"))]
+        assert_eq!(
+            section_info.to_string(),
+            "1030 samples every 120s from 1637924839 to 1638048439"
+        );
+    }
+
+    #[test]
+    fn maps_response_codes() {
+        assert_eq!(ResponseCode::from_byte(1), ResponseCode::Ok);
+        assert_eq!(ResponseCode::from_byte(3), ResponseCode::Busy);
+        assert_eq!(ResponseCode::from_byte(5), ResponseCode::Unsupported);
+        assert_eq!(ResponseCode::from_byte(0x42), ResponseCode::Unknown(0x42));
+        assert_eq!(ResponseCode::from_response(&[]), None);
+
+        // A bare status byte is reported as such rather than as truncated.
+        assert_eq!(
+            MeterSectionInfo::from_response(&[3]),
+            Err(ParseError::BadResponseCode(3))
+        );
+    }
+
+    #[test]
+    fn rejects_humidity_above_100() {
+        let out_of_range = ParseError::OutOfRange {
+            field: "humidity",
+            value: 0x73,
+        };
+        assert_eq!(
+            MeterValue::from_data(&[105, 0, 228, 9, 152, 0x73]).unwrap_err(),
+            out_of_range
+        );
+        assert_eq!(
+            MeterSampleValue::from_response(&[1, 152, 0x73, 119, 152, 40]).unwrap_err(),
+            out_of_range
+        );
+        assert_eq!(
+            MeterSampleValue::from_response(&[1, 152, 40, 119, 152, 0x73]).unwrap_err(),
+            out_of_range
+        );
+        assert!(MeterValue::from_data(&[105, 0, 228, 9, 152, 100]).is_ok());
+    }
+
+    #[test]
+    fn decodes_temperature_sign() {
+        let parse = |degrees, tenths| {
+            MeterValue::from_data(&[105, 0, 228, tenths, degrees, 40])
+                .unwrap()
+                .temperature
+                .0
+        };
+
+        assert_eq!(parse(0x80, 0).to_bits(), 0.0f32.to_bits());
+        // A cleared sign bit on a zero reading must not turn into -0.0.
+        assert_eq!(parse(0x00, 0).to_bits(), 0.0f32.to_bits());
+        assert!((parse(0x80, 1) - 0.1).abs() < f32::EPSILON);
+        assert!((parse(0x00, 1) + 0.1).abs() < f32::EPSILON);
+        assert!((parse(0x05, 3) + 5.3).abs() < f32::EPSILON);
+
+        let samples = MeterSampleValue::from_response(&[1, 0x05, 40, 0x31, 0x80, 40]).unwrap();
+        assert!((samples[0].as_ref().unwrap().temperature.0 + 5.3).abs() < f32::EPSILON);
+        assert!((samples[1].as_ref().unwrap().temperature.0 - 0.1).abs() < f32::EPSILON);
+    }
+
     #[test]
     fn parses_sample_info() {
         let response = vec![1, 152, 40, 119, 152, 40, 152, 40, 120, 152, 40];
         let result = MeterSampleValue::from_response(&response);
         assert_eq!(
             result,
-            Some(
-                vec![(24.7, 40), (24.7, 40), (24.7, 40), (24.8, 40)]
-                    .into_iter()
-                    .map(|(temperature, humidity)| MeterSampleValue {
-                        temperature,
-                        humidity
-                    })
-                    .collect()
-            )
+            Ok(vec![(24.7, 40), (24.7, 40), (24.7, 40), (24.8, 40)]
+                .into_iter()
+                .map(|(temperature, humidity)| Some(MeterSampleValue {
+                    temperature: Celsius(temperature),
+                    humidity: Humidity(humidity),
+                    flags: SampleFlags::default(),
+                }))
+                .collect())
         );
     }
 
+    #[test]
+    fn decodes_sample_pairs_by_mode() {
+        let response = [1, 152, 40, 119, 152, 40, 152, 40, 120, 152, 40];
+        let decode = |mode| {
+            MeterSampleValue::from_response_with(&response, mode)
+                .unwrap()
+                .into_iter()
+                .map(|sample| sample.map(|sample| sample.temperature.0))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            MeterSampleValue::from_response_with(&response, PairMode::Both),
+            MeterSampleValue::from_response(&response)
+        );
+        assert_eq!(decode(PairMode::First), [Some(24.7), Some(24.7)]);
+        assert_eq!(decode(PairMode::Average), [Some(24.7), Some(24.8)]);
+
+        // A gap in one half of a pair leaves the other.
+        let response = [1, 152, 41, 0xff, 0x7f, 0x7f, 0xff, 0xff, 0xff, 0xff, 0xff];
+        let samples = MeterSampleValue::from_response_with(&response, PairMode::Average).unwrap();
+        assert_eq!(samples[0].unwrap().humidity, Humidity(41));
+        assert_eq!(samples[1], None);
+        assert_eq!(
+            MeterSampleValue::from_response_with(&[2], PairMode::First),
+            Err(ParseError::BadResponseCode(2))
+        );
+    }
+
+    #[test]
+    fn flags_suspect_samples() {
+        let flags = |response: &[u8]| {
+            MeterSampleValue::from_response(response)
+                .unwrap()
+                .into_iter()
+                .map(|sample| sample.unwrap().flags)
+                .collect::<Vec<_>>()
+        };
+
+        assert!(flags(&[1, 152, 40, 119, 152, 40])
+            .iter()
+            .all(|flags| flags.is_empty()));
+
+        // Tenths of 10 in the first sample, a humidity with its high bit set
+        // in the second.
+        let suspect = flags(&[1, 152, 40, 0xa7, 152, 0xa8]);
+        assert_eq!(suspect[0], SampleFlags::SUSPECT_TEMPERATURE);
+        assert!(suspect[1].suspect_humidity() && !suspect[1].suspect_temperature());
+
+        // 90°C and -50°C are beyond the sensor's range.
+        let suspect = flags(&[1, 0xda, 40, 0, 50, 40]);
+        assert!(suspect.iter().all(|flags| flags.suspect_temperature()));
+
+        // Averaging a pair keeps the flags of both samples.
+        let samples =
+            MeterSampleValue::from_response_with(&[1, 152, 40, 0xa7, 152, 0xa8], PairMode::Average)
+                .unwrap();
+        assert_eq!(
+            samples[0].unwrap().flags,
+            SampleFlags::SUSPECT_TEMPERATURE.union(SampleFlags::SUSPECT_HUMIDITY)
+        );
+    }
+
+    #[test]
+    fn decodes_gaps_as_no_data() {
+        let response = [1, 0xff, 0xff, 0xff, 0xff, 0xff, 152, 40, 0x7f, 0x7f, 0x7f];
+        let samples = MeterSampleValue::from_response(&response).unwrap();
+        assert_eq!(samples.len(), 4);
+        assert_eq!(samples[0], None);
+        assert_eq!(samples[1], None);
+        assert_eq!(
+            samples[2],
+            Some(MeterSampleValue {
+                temperature: Celsius(24.7),
+                humidity: Humidity(40),
+                flags: SampleFlags::default(),
+            })
+        );
+        assert_eq!(samples[3], None);
+    }
+
+    #[test]
+    fn decodes_blocks_lazily_in_order() {
+        let first = [1, 152, 40, 119, 152, 41];
+        let second = [1, 152, 42, 120, 152, 43];
+        let humidities = MeterSampleValue::decode_blocks([&first[..], &[2], &second[..]])
+            .map(|sample| sample.map(|sample| sample.unwrap().humidity.0))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            humidities,
+            vec![
+                Ok(40),
+                Ok(41),
+                Err(ParseError::BadResponseCode(2)),
+                Ok(42),
+                Ok(43)
+            ]
+        );
+
+        let mut samples = MeterSampleValue::decode_blocks(core::iter::repeat(&first[..]));
+        assert!(samples.nth(1000).unwrap().is_ok());
+    }
+
+    #[test]
+    fn rejects_truncated_last_sample_group() {
+        // One byte short of a second complete group.
+        let response = [1, 152, 40, 119, 152, 40, 152, 40, 120, 152];
+        assert_eq!(
+            MeterSampleValue::first_value(&response[6..8]),
+            Err(ParseError::TooShort {
+                expected: 3,
+                got: 2
+            })
+        );
+        assert_eq!(
+            MeterSampleValue::second_value(&response[6..]),
+            Err(ParseError::TooShort {
+                expected: 5,
+                got: 4
+            })
+        );
+    }
+
+    /// The advertisement of an Outdoor Meter at 21.6°C and 51%, with its
+    /// display in °C.
+    const OUTDOOR_SERVICE_DATA: [u8; 3] = [0x77, 0x00, 0xe4];
+    const OUTDOOR_MANUFACTURER_DATA: [u8; 13] = [
+        0xd3, 0x4e, 0x07, 0x01, 0x02, 0x03, 0x2d, 0x0b, 0x03, 0x00, 0x06, 0x95, 0x33,
+    ];
+
+    #[test]
+    fn parses_outdoor_meter_advertisements() {
+        let expected = MeterValue {
+            temperature: Celsius(21.6),
+            humidity: Humidity(51),
+            battery: 100,
+            display_unit: TempUnit::Celsius,
+        };
+        assert_eq!(
+            MeterModel::from_device_type(b'w'),
+            Some(MeterModel::OutdoorMeter)
+        );
+        assert_eq!(
+            MeterValue::from_outdoor_data(&OUTDOOR_SERVICE_DATA, &OUTDOOR_MANUFACTURER_DATA),
+            Ok(expected)
+        );
+
+        let mut freezing = OUTDOOR_MANUFACTURER_DATA;
+        freezing[11] = 0x05;
+        freezing[12] |= 0x80;
+        let value = MeterValue::from_outdoor_data(&OUTDOOR_SERVICE_DATA, &freezing).unwrap();
+        assert_eq!(value.temperature, Celsius(-5.6));
+        assert_eq!(value.humidity, Humidity(51));
+        assert_eq!(value.display_unit, TempUnit::Fahrenheit);
+
+        assert_eq!(
+            MeterValue::from_outdoor_data(&OUTDOOR_SERVICE_DATA, &OUTDOOR_MANUFACTURER_DATA[..12]),
+            Err(ParseError::TooShort {
+                expected: 13,
+                got: 12
+            })
+        );
+        // The outdoor layout doesn't fit the one of the other meters.
+        assert!(MeterValue::from_data(&[b'w', 0, 228, 9, 152, 40]).is_err());
+    }
+
+    #[test]
+    fn detects_the_model_of_advertisements() {
+        assert_eq!(
+            MeterValue::from_advertisement(&OUTDOOR_SERVICE_DATA, Some(&OUTDOOR_MANUFACTURER_DATA))
+                .map(|value| value.temperature),
+            Ok(Celsius(21.6))
+        );
+        assert_eq!(
+            MeterValue::from_advertisement(&OUTDOOR_SERVICE_DATA, None),
+            Err(ParseError::TooShort {
+                expected: 13,
+                got: 0
+            })
+        );
+        assert_eq!(
+            MeterValue::from_advertisement(&[105, 0, 228, 9, 152, 40], None)
+                .map(|value| value.temperature),
+            Ok(Celsius(24.9))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_frames() {
+        assert_eq!(
+            MeterValue::from_data(&[105, 0, 228]),
+            Err(ParseError::TooShort {
+                expected: 6,
+                got: 3
+            })
+        );
+        assert_eq!(
+            MeterValue::from_data(&[104, 0, 228, 9, 152, 40]),
+            Err(ParseError::BadResponseCode(104))
+        );
+        assert_eq!(
+            MeterSampleValue::from_response(&[2, 152, 40, 119, 152, 40]),
+            Err(ParseError::BadResponseCode(2))
+        );
+        assert_eq!(
+            MeterSampleValue::from_response(&[1, 152, 40, 119, 152, 40, 152]),
+            Err(ParseError::MisalignedLength(7))
+        );
+        assert_eq!(
+            MeterSectionInfo::from_response(&[1, 97, 160]),
+            Err(ParseError::TooShort {
+                expected: 13,
+                got: 3
+            })
+        );
+    }
+
+    #[test]
+    fn verifies_checksums() {
+        let response = vec![1, 152, 40, 119, 152, 40, 248];
+        assert!(verify_checksum(&response));
+        assert_eq!(
+            MeterSampleValue::from_response_checked(&response),
+            Ok(vec![
+                Some(MeterSampleValue {
+                    temperature: Celsius(24.7),
+                    humidity: Humidity(40),
+                    flags: SampleFlags::default(),
+                }),
+                Some(MeterSampleValue {
+                    temperature: Celsius(24.7),
+                    humidity: Humidity(40),
+                    flags: SampleFlags::default(),
+                })
+            ])
+        );
+
+        let corrupted = vec![1, 152, 41, 119, 152, 40, 248];
+        assert!(!verify_checksum(&corrupted));
+        assert_eq!(
+            MeterSampleValue::from_response_checked(&corrupted),
+            Err(ParseError::BadChecksum)
+        );
+        assert!(!verify_checksum(&[]));
+    }
+
+    #[test]
+    fn converts_to_fahrenheit() {
+        let value = MeterValue {
+            temperature: Celsius(24.9),
+            humidity: Humidity(40),
+            battery: 100,
+            display_unit: TempUnit::Celsius,
+        };
+        assert!((value.to_fahrenheit() - 76.8).abs() < f32::EPSILON);
+
+        let sample = MeterSampleValue {
+            temperature: Celsius(-3.5),
+            humidity: Humidity(40),
+            flags: SampleFlags::default(),
+        };
+        assert!((sample.to_fahrenheit() - 25.7).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn rejects_truncated_sample_info() {
+        let response = vec![1, 152, 40, 119, 152, 40, 152, 40, 120, 152];
+        let result = MeterSampleValue::from_response(&response);
+        assert_eq!(result, Err(ParseError::MisalignedLength(10)));
+    }
+
+    #[test]
+    fn computes_dew_point() {
+        let value = MeterValue {
+            temperature: Celsius(24.9),
+            humidity: Humidity(40),
+            battery: 100,
+            display_unit: TempUnit::Celsius,
+        };
+        assert!((value.dew_point() - 10.4).abs() < 0.1);
+
+        let sample = MeterSampleValue {
+            temperature: Celsius(20.0),
+            humidity: Humidity(0),
+            flags: SampleFlags::default(),
+        };
+        assert!(sample.dew_point().is_finite());
+    }
+
+    #[test]
+    fn computes_heat_index() {
+        // NWS heat index chart: 90°F at 50% feels like 95°F, 80°F at 40%
+        // feels like 80°F.
+        let value = MeterValue {
+            temperature: Celsius(32.2),
+            humidity: Humidity(50),
+            battery: 100,
+            display_unit: TempUnit::Celsius,
+        };
+        assert!((value.heat_index() - 35.0).abs() < 0.3);
+
+        let sample = MeterSampleValue {
+            temperature: Celsius(26.7),
+            humidity: Humidity(40),
+            flags: SampleFlags::default(),
+        };
+        assert!((sample.heat_index() - 26.7).abs() < 0.3);
+    }
+
+    #[test]
+    fn computes_absolute_humidity() {
+        // Saturated air holds 17.3 g/m³ at 20°C.
+        let sample = MeterSampleValue {
+            temperature: Celsius(20.0),
+            humidity: Humidity(50),
+            flags: SampleFlags::default(),
+        };
+        assert!((sample.absolute_humidity() - 8.65).abs() < 0.05);
+
+        let value = MeterValue {
+            temperature: Celsius(30.0),
+            humidity: Humidity(100),
+            battery: 100,
+            display_unit: TempUnit::Celsius,
+        };
+        assert!((value.absolute_humidity() - 30.4).abs() < 0.1);
+    }
+
     #[test]
     fn parse_section_info() {
         let response = vec![1, 97, 160, 191, 231, 97, 162, 162, 63, 4, 6, 0, 120];
         let result = MeterSectionInfo::from_response(&response);
         assert_eq!(
             result,
-            Some(MeterSectionInfo {
+            Ok(MeterSectionInfo {
                 start_time: 1_637_924_839,
                 end_time: 1_638_048_319,
                 interval: 120,
@@ -166,4 +1580,74 @@ mod tests {
             })
         );
     }
+
+    #[test]
+    fn computes_sample_timestamps() {
+        let section_info = MeterSectionInfo {
+            start_time: 1_637_924_839,
+            end_time: 1_638_048_319,
+            interval: 120,
+            data_length: 1030,
+        };
+
+        let timestamps: Vec<i64> = section_info.timestamps(6).collect();
+        assert_eq!(timestamps.len(), 6);
+        assert_eq!(timestamps[0], 1_638_047_719);
+        assert_eq!(
+            timestamps.last().copied(),
+            Some(i64::from(section_info.end_time))
+        );
+
+        assert_eq!(
+            section_info.timestamps(2000).next(),
+            Some(i64::from(section_info.start_time))
+        );
+    }
+
+    #[test]
+    fn rejects_section_info_with_zero_interval() {
+        let response = vec![1, 97, 160, 191, 231, 97, 162, 162, 63, 4, 6, 0, 0];
+        let result = MeterSectionInfo::from_response(&response);
+        assert_eq!(result, Err(ParseError::ZeroInterval));
+    }
+
+    #[test]
+    fn parses_device_info() {
+        let response = vec![1, 25, 2];
+        let result = DeviceInfo::from_response(&response);
+        assert_eq!(
+            result,
+            Ok(DeviceInfo {
+                firmware: "2.5".to_string(),
+                hardware: 2
+            })
+        );
+    }
+
+    #[test]
+    fn parses_device_name() {
+        assert_eq!(
+            DeviceName::from_response(b"\x01Garage\0\0\0"),
+            Ok(DeviceName("Garage".to_string()))
+        );
+        assert_eq!(
+            DeviceName::from_response(b"\x01Gar\xffge"),
+            Ok(DeviceName("Gar\u{fffd}ge".to_string()))
+        );
+        assert_eq!(
+            DeviceName::from_response(&[1, 0, 0]),
+            Ok(DeviceName(String::new()))
+        );
+        assert_eq!(
+            DeviceName::from_response(&[3]),
+            Err(ParseError::BadResponseCode(3))
+        );
+        assert_eq!(
+            DeviceName::from_response(&[]),
+            Err(ParseError::TooShort {
+                expected: 1,
+                got: 0
+            })
+        );
+    }
 }
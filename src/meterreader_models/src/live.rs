@@ -0,0 +1,54 @@
+use bluer::{Adapter, AdapterEvent, Address, Uuid};
+use futures::{Stream, StreamExt};
+
+use crate::MeterValue;
+
+/// The service the meters advertise their current readings for.
+///
+/// 0000fd3d-0000-1000-8000-00805f9b34fb
+pub const ADVERTISEMENT_SERVICE_UUID: Uuid =
+    Uuid::from_u128(0x0000_fd3d_0000_1000_8000_0080_5f9b_34fb_u128);
+
+/// The company identifier the Outdoor Meter sends its readings as
+/// manufacturer data under.
+pub const MANUFACTURER_ID: u16 = 0x0969;
+
+/// Starts discovery and streams the current readings of meters as their
+/// advertisements arrive.
+///
+/// Only readings from the device with the address `filter` are yielded if
+/// one is given. Devices that aren't meters are skipped.
+///
+/// # Errors
+///
+/// Returns an error if discovery can't be started.
+pub async fn live_values(
+    adapter: &Adapter,
+    filter: Option<Address>,
+) -> bluer::Result<impl Stream<Item = (Address, MeterValue)>> {
+    let adapter = adapter.clone();
+    let events = adapter.discover_devices_with_changes().await?;
+    Ok(events.filter_map(move |event| {
+        let adapter = adapter.clone();
+        async move {
+            let AdapterEvent::DeviceAdded(addr) = event else {
+                return None;
+            };
+            if filter.is_some_and(|wanted| wanted != addr) {
+                return None;
+            }
+            let device = adapter.device(addr).ok()?;
+            let service_data = device.service_data().await.ok()??;
+            let manufacturer_data = device.manufacturer_data().await.ok()?;
+            let value = MeterValue::from_advertisement(
+                service_data.get(&ADVERTISEMENT_SERVICE_UUID)?,
+                manufacturer_data
+                    .as_ref()
+                    .and_then(|data| data.get(&MANUFACTURER_ID))
+                    .map(Vec::as_slice),
+            )
+            .ok()?;
+            Some((addr, value))
+        }
+    }))
+}
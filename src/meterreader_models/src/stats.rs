@@ -0,0 +1,135 @@
+use alloc::vec::Vec;
+
+use crate::{float, Celsius, Humidity, MeterSampleValue, SampleFlags};
+
+/// Summary statistics of a series of values.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Summary {
+    pub count: usize,
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    /// The population standard deviation.
+    pub std_dev: f32,
+}
+
+impl Summary {
+    /// Summarizes the given values, returning `None` if there are none.
+    #[must_use]
+    pub fn of(values: impl IntoIterator<Item = f32>) -> Option<Summary> {
+        let values = values.into_iter().map(f64::from).collect::<Vec<_>>();
+        if values.is_empty() {
+            return None;
+        }
+
+        #[allow(clippy::cast_precision_loss)]
+        let count = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / count;
+        let variance = values
+            .iter()
+            .map(|value| (value - mean) * (value - mean))
+            .sum::<f64>()
+            / count;
+        let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+
+        #[allow(clippy::cast_possible_truncation)]
+        Some(Summary {
+            count: values.len(),
+            min: min as f32,
+            max: max as f32,
+            mean: mean as f32,
+            std_dev: float::sqrt(variance) as f32,
+        })
+    }
+}
+
+/// Drops the gaps, i.e. samples without data, from samples given as pairs of
+/// Unix timestamps and values.
+#[must_use]
+pub fn without_gaps(samples: &[(i64, Option<MeterSampleValue>)]) -> Vec<(i64, MeterSampleValue)> {
+    samples
+        .iter()
+        .filter_map(|&(timestamp, sample)| Some((timestamp, sample?)))
+        .collect()
+}
+
+/// Groups samples, given as pairs of Unix timestamps and values, into buckets
+/// of `seconds` length and averages each bucket.
+///
+/// Buckets start at multiples of `seconds` and are identified by their start.
+/// Samples must be sorted by time; empty buckets are left out.
+///
+/// # Panics
+///
+/// Panics if `seconds` isn't positive.
+#[must_use]
+pub fn bucket(samples: &[(i64, MeterSampleValue)], seconds: i64) -> Vec<(i64, MeterSampleValue)> {
+    assert!(seconds > 0, "bucket length must be positive");
+
+    samples
+        .chunk_by(|(a, _), (b, _)| a.div_euclid(seconds) == b.div_euclid(seconds))
+        .map(|bucket| {
+            let start = bucket[0].0.div_euclid(seconds) * seconds;
+            let temperature = Summary::of(bucket.iter().map(|(_, value)| value.temperature.0))
+                .map_or(0.0, |summary| summary.mean);
+            let humidity = Summary::of(bucket.iter().map(|(_, value)| f32::from(value.humidity)))
+                .map_or(0.0, |summary| summary.mean);
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let value = MeterSampleValue {
+                temperature: Celsius(float::round(temperature * 10.0) / 10.0),
+                humidity: Humidity(float::round(humidity) as u8),
+                flags: bucket
+                    .iter()
+                    .fold(SampleFlags::default(), |flags, (_, value)| {
+                        flags.union(value.flags)
+                    }),
+            };
+            (start, value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stats::{bucket, Summary};
+    use crate::{Celsius, Humidity, MeterSampleValue, SampleFlags};
+
+    #[test]
+    fn summarizes_values() {
+        let summary = Summary::of([2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]).unwrap();
+        assert_eq!(summary.count, 8);
+        assert!((summary.min - 2.0).abs() < f32::EPSILON);
+        assert!((summary.max - 9.0).abs() < f32::EPSILON);
+        assert!((summary.mean - 5.0).abs() < f32::EPSILON);
+        assert!((summary.std_dev - 2.0).abs() < f32::EPSILON);
+
+        assert_eq!(Summary::of([]), None);
+    }
+
+    #[test]
+    fn averages_buckets() {
+        let sample = |temperature, humidity| MeterSampleValue {
+            temperature: Celsius(temperature),
+            humidity: Humidity(humidity),
+            flags: SampleFlags::default(),
+        };
+        let samples = [
+            (1200, sample(20.0, 40)),
+            (1320, sample(21.0, 41)),
+            (1440, sample(22.0, 44)),
+            (1800, sample(23.0, 50)),
+            // Partial final bucket.
+            (2400, sample(25.5, 60)),
+        ];
+
+        assert_eq!(
+            bucket(&samples, 600),
+            vec![
+                (1200, sample(21.0, 42)),
+                (1800, sample(23.0, 50)),
+                (2400, sample(25.5, 60)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,101 @@
+use core::fmt;
+
+use crate::celsius_to_fahrenheit;
+
+/// A temperature in degrees Celsius.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct Celsius(pub f32);
+
+impl Celsius {
+    /// Converts the temperature to degrees Fahrenheit, rounded to one decimal.
+    #[must_use]
+    pub fn as_fahrenheit(self) -> f32 {
+        celsius_to_fahrenheit(self.0)
+    }
+}
+
+impl From<f32> for Celsius {
+    fn from(celsius: f32) -> Celsius {
+        Celsius(celsius)
+    }
+}
+
+impl From<Celsius> for f32 {
+    fn from(celsius: Celsius) -> f32 {
+        celsius.0
+    }
+}
+
+impl fmt::Display for Celsius {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}°C", self.0)
+    }
+}
+
+/// The unit a meter's display shows temperatures in.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum TempUnit {
+    #[default]
+    Celsius,
+    Fahrenheit,
+}
+
+impl fmt::Display for TempUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TempUnit::Celsius => write!(f, "°C"),
+            TempUnit::Fahrenheit => write!(f, "°F"),
+        }
+    }
+}
+
+/// A relative humidity in percent.
+#[derive(Clone, Copy, Debug, Default, Eq, Ord, PartialEq, PartialOrd)]
+pub struct Humidity(pub u8);
+
+impl Humidity {
+    /// Returns the relative humidity as a ratio between 0 and 1.
+    #[must_use]
+    pub fn as_ratio(self) -> f32 {
+        f32::from(self.0) / 100.0
+    }
+}
+
+impl From<u8> for Humidity {
+    fn from(percent: u8) -> Humidity {
+        Humidity(percent)
+    }
+}
+
+impl From<Humidity> for u8 {
+    fn from(humidity: Humidity) -> u8 {
+        humidity.0
+    }
+}
+
+impl From<Humidity> for f32 {
+    fn from(humidity: Humidity) -> f32 {
+        f32::from(humidity.0)
+    }
+}
+
+impl fmt::Display for Humidity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}%", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Celsius, Humidity};
+
+    #[test]
+    fn converts_units() {
+        assert!((Celsius(24.9).as_fahrenheit() - 76.8).abs() < f32::EPSILON);
+        assert!((Humidity(40).as_ratio() - 0.4).abs() < f32::EPSILON);
+        assert_eq!(Celsius::from(-5.3), Celsius(-5.3));
+        assert_eq!(u8::from(Humidity::from(40)), 40);
+        assert_eq!(Celsius(24.9).to_string(), "24.9°C");
+        assert_eq!(Humidity(40).to_string(), "40%");
+    }
+}